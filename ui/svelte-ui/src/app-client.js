@@ -78,14 +78,26 @@ export class AppClient {
         return this.#appClient.callZome(params);
     }
 
-    async startGame(gameCode) {
+    async setReady(gameCode, ready) {
+        const params = {
+            cap: null,
+            cell_id: this.#cellId,
+            zome_name: 'tragedy_of_commons',
+            fn_name: 'set_player_ready',
+            provenance: this.#agentPubKey,
+            payload: { game_code: gameCode, ready: ready }
+        };
+        return this.#appClient.callZome(params);
+    }
+
+    async startGame(gameCode, force, teams) {
         const params = {
             cap: null,
             cell_id: this.#cellId,
             zome_name: 'tragedy_of_commons',
             fn_name: 'start_game_session_with_code',
             provenance: this.#agentPubKey,
-            payload: gameCode
+            payload: { game_code: gameCode, force: !!force, teams: teams || [] }
         };
         return this.#appClient.callZome(params);
     }
@@ -140,6 +152,149 @@ export class AppClient {
             return this.#appClient.callZome(params);
         }
 
+    async roundStatus(round_hash) {
+        const params = {
+            cap: null,
+            cell_id: this.#cellId,
+            zome_name: 'tragedy_of_commons',
+            fn_name: 'round_status',
+            provenance: this.#agentPubKey,
+            payload: round_hash
+        };
+        return this.#appClient.callZome(params);
+    }
+
+    async getGameHistory(game_session_hash) {
+        const params = {
+            cap: null,
+            cell_id: this.#cellId,
+            zome_name: 'tragedy_of_commons',
+            fn_name: 'get_game_history',
+            provenance: this.#agentPubKey,
+            payload: game_session_hash
+        };
+        return this.#appClient.callZome(params);
+    }
+
+    async getGameResults(game_session_hash) {
+        const params = {
+            cap: null,
+            cell_id: this.#cellId,
+            zome_name: 'tragedy_of_commons',
+            fn_name: 'get_game_results',
+            provenance: this.#agentPubKey,
+            payload: game_session_hash
+        };
+        return this.#appClient.callZome(params);
+    }
+
+    async getLeaderboard(group, sort_by, offset, limit) {
+        const params = {
+            cap: null,
+            cell_id: this.#cellId,
+            zome_name: 'tragedy_of_commons',
+            fn_name: 'get_leaderboard',
+            provenance: this.#agentPubKey,
+            payload: { group, sort_by, offset, limit }
+        };
+        return this.#appClient.callZome(params);
+    }
+
+    async setLobbyGroup(gameCode, group) {
+        const params = {
+            cap: null,
+            cell_id: this.#cellId,
+            zome_name: 'tragedy_of_commons',
+            fn_name: 'set_lobby_group',
+            provenance: this.#agentPubKey,
+            payload: { game_code: gameCode, group }
+        };
+        return this.#appClient.callZome(params);
+    }
+
+    async exportSession(game_session_hash) {
+        const params = {
+            cap: null,
+            cell_id: this.#cellId,
+            zome_name: 'tragedy_of_commons',
+            fn_name: 'export_session',
+            provenance: this.#agentPubKey,
+            payload: game_session_hash
+        };
+        return this.#appClient.callZome(params);
+    }
+
+    async exportSessionCsv(game_session_hash) {
+        const params = {
+            cap: null,
+            cell_id: this.#cellId,
+            zome_name: 'tragedy_of_commons',
+            fn_name: 'export_session_csv',
+            provenance: this.#agentPubKey,
+            payload: game_session_hash
+        };
+        return this.#appClient.callZome(params);
+    }
+
+    async verifySession(game_session_hash) {
+        const params = {
+            cap: null,
+            cell_id: this.#cellId,
+            zome_name: 'tragedy_of_commons',
+            fn_name: 'verify_session',
+            provenance: this.#agentPubKey,
+            payload: game_session_hash
+        };
+        return this.#appClient.callZome(params);
+    }
+
+    async setLobbyGameParams(gameCode, game_params) {
+        const params = {
+            cap: null,
+            cell_id: this.#cellId,
+            zome_name: 'tragedy_of_commons',
+            fn_name: 'set_lobby_game_params',
+            provenance: this.#agentPubKey,
+            payload: { game_code: gameCode, game_params }
+        };
+        return this.#appClient.callZome(params);
+    }
+
+    async createTournament(gameCode, group_size, stages) {
+        const params = {
+            cap: null,
+            cell_id: this.#cellId,
+            zome_name: 'tragedy_of_commons',
+            fn_name: 'create_tournament',
+            provenance: this.#agentPubKey,
+            payload: { game_code: gameCode, group_size, stages }
+        };
+        return this.#appClient.callZome(params);
+    }
+
+    async advanceTournament(tournament_hash) {
+        const params = {
+            cap: null,
+            cell_id: this.#cellId,
+            zome_name: 'tragedy_of_commons',
+            fn_name: 'advance_tournament',
+            provenance: this.#agentPubKey,
+            payload: tournament_hash
+        };
+        return this.#appClient.callZome(params);
+    }
+
+    async getTournamentStandings(tournament_hash) {
+        const params = {
+            cap: null,
+            cell_id: this.#cellId,
+            zome_name: 'tragedy_of_commons',
+            fn_name: 'get_tournament_standings',
+            provenance: this.#agentPubKey,
+            payload: tournament_hash
+        };
+        return this.#appClient.callZome(params);
+    }
 
     async getMyOwnedSessions(amount, prev_round_hash) {
             const params = {
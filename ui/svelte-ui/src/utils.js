@@ -60,4 +60,18 @@ export function shortenBase64(hash) {
 export function shorten(input, begin=3, end=5) {
   let shortened = input.substring(0, begin) + "..." + input.substring(input.length - end);
   return shortened;
-}
\ No newline at end of file
+}
+// zome errors come as a JSON string { code, message } inside of the ribosome error,
+// so the UI can branch on the code instead of matching the message text
+export function parseZomeError(error) {
+  const data = error?.data?.data ?? error?.data ?? error?.message;
+  try {
+    const parsed = JSON.parse(data);
+    if (parsed && parsed.code) {
+      return parsed;
+    }
+  } catch (e) {
+    // not a typed zome error
+  }
+  return { code: "UNKNOWN", message: String(data) };
+}
@@ -0,0 +1,153 @@
+//! Runs many games played by bots and prints how they ended, e.g.
+//!
+//! cargo run --release --bin simulate -- --games 10000 --strategies greedy,tit-for-tat,random
+use std::collections::BTreeMap;
+use std::env;
+use std::process;
+use tragedy_of_commons_rules::{simulate_with_seed, BotStrategy, GameParams, MIN_PLAYERS};
+
+const USAGE: &str = "\
+Usage: simulate [options]
+
+Options:
+    --games N             number of games to simulate (default 1000)
+    --strategies LIST     comma separated strategy of every seat: sustainable, greedy,
+                          tit-for-tat or random, at least two seats
+                          (default sustainable,greedy)
+    --rounds N            upper limit on rounds in a game (default num_rounds)
+    --num-rounds N        num_rounds of the game params (default 3)
+    --start-amount N      resources in the commons at the start (default 100)
+    --regeneration F      regeneration factor of the commons (default 1.1)
+    --help                print this message";
+
+struct Options {
+    games: u32,
+    strategies: Vec<BotStrategy>,
+    rounds: Option<u32>,
+    params: GameParams,
+}
+
+fn parse_strategy(name: &str) -> Result<BotStrategy, String> {
+    match name.trim().to_lowercase().replace('_', "-").as_str() {
+        "sustainable" => Ok(BotStrategy::Sustainable),
+        "greedy" => Ok(BotStrategy::Greedy),
+        "tit-for-tat" => Ok(BotStrategy::TitForTat),
+        "random" => Ok(BotStrategy::Random),
+        other => Err(format!("unknown strategy {}", other)),
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or(format!("{} needs a value", option))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {} for {}", value, option))
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        games: 1000,
+        strategies: vec![BotStrategy::Sustainable, BotStrategy::Greedy],
+        rounds: None,
+        params: GameParams::default(),
+    };
+    while let Some(option) = args.next() {
+        match option.as_str() {
+            "--games" => options.games = parse_value(&option, args.next())?,
+            "--strategies" => {
+                let list: String = parse_value(&option, args.next())?;
+                options.strategies = list
+                    .split(',')
+                    .map(parse_strategy)
+                    .collect::<Result<_, _>>()?;
+            }
+            "--rounds" => options.rounds = Some(parse_value(&option, args.next())?),
+            "--num-rounds" => options.params.num_rounds = parse_value(&option, args.next())?,
+            "--start-amount" => options.params.start_amount = parse_value(&option, args.next())?,
+            "--regeneration" => {
+                options.params.regeneration_factor = parse_value(&option, args.next())?
+            }
+            "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            other => return Err(format!("unknown option {}", other)),
+        }
+    }
+    // every seat is a bot, so the player limits only have to fit the strategies
+    options.params.min_players = MIN_PLAYERS;
+    options.params.max_players = options.strategies.len() as u32;
+    options
+        .params
+        .check_player_limits()
+        .and_then(|_| options.params.check_round_length())
+        .map_err(|e| e.to_string())?;
+    Ok(options)
+}
+
+fn percent(count: u32, total: u32) -> f32 {
+    count as f32 * 100.0 / total.max(1) as f32
+}
+
+fn main() {
+    let options = match parse_options(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            process::exit(1);
+        }
+    };
+    let rounds = options.rounds.unwrap_or(options.params.num_rounds);
+
+    let mut survived = 0;
+    let mut rounds_played: BTreeMap<u32, u32> = BTreeMap::new();
+    let mut total_harvest: i64 = 0;
+    let mut gini_sum = 0.0;
+    let mut harvest_per_seat = vec![0i64; options.strategies.len()];
+    for game in 0..options.games {
+        let seed = game.to_le_bytes();
+        let result = simulate_with_seed(&options.params, &options.strategies, rounds, &seed);
+        if result.commons_survived {
+            survived += 1;
+        }
+        *rounds_played.entry(result.rounds_played).or_insert(0) += 1;
+        total_harvest += result.total_harvest as i64;
+        gini_sum += result.gini_coefficient;
+        for (seat, harvest) in result.harvest_per_seat.iter().enumerate() {
+            harvest_per_seat[seat] += *harvest as i64;
+        }
+    }
+
+    let games = options.games;
+    println!("{} games, {} rounds at most", games, rounds);
+    println!(
+        "commons survived: {} ({:.1}%), lost: {} ({:.1}%)",
+        survived,
+        percent(survived, games),
+        games - survived,
+        percent(games - survived, games)
+    );
+    println!("rounds played:");
+    for (rounds, count) in rounds_played.iter() {
+        println!(
+            "  {:>3}: {:>7} ({:.1}%)",
+            rounds,
+            count,
+            percent(*count, games)
+        );
+    }
+    println!(
+        "average total harvest: {:.1}, average gini: {:.3}",
+        total_harvest as f64 / games.max(1) as f64,
+        gini_sum / games.max(1) as f32
+    );
+    println!("average harvest per seat:");
+    for (seat, strategy) in options.strategies.iter().enumerate() {
+        println!(
+            "  seat {} ({:?}): {:.1}",
+            seat + 1,
+            strategy,
+            harvest_per_seat[seat] as f64 / games.max(1) as f64
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use crate::params::{seeded_random, GameParams};
+use crate::round::RoundState;
+use crate::stats::{sustainable_harvest, ResourceAmount};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Resources taken by every bot, by bot name
+pub type BotStats = BTreeMap<String, ResourceAmount>;
+
+/// How the bot decides how much to take from the commons
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum BotStrategy {
+    /// Takes its share of what the commons can regrow, so it never shrinks
+    Sustainable,
+    /// Takes its share of everything that is left
+    Greedy,
+    /// Takes what human players took on average in the previous round, but never more
+    /// than its greedy share, and plays sustainable in the first round
+    TitForTat,
+    /// Takes anything from nothing up to its greedy share
+    Random,
+}
+
+/// Seat in the game taken by a bot instead of a player
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct BotSeat {
+    pub name: String,
+    pub strategy: BotStrategy,
+}
+
+/// Calculates the moves of every bot for the round that follows the last one. Bots only
+/// look at the state of the last round and at the seed, so every agent closing or
+/// validating the round gets exactly the same moves
+pub fn bot_moves<P: Ord>(
+    last_round_num: u32,
+    last_state: &RoundState<P>,
+    seed: &[u8],
+    params: &GameParams,
+    human_players: usize,
+) -> BotStats {
+    let seats = (human_players + params.bots.len()).max(1) as f32;
+    let resources = last_state.resources_left.max(0);
+    let sustainable_share =
+        (sustainable_harvest(resources, params.regeneration_factor) / seats) as ResourceAmount;
+    let greedy_share = (resources as f32 / seats) as ResourceAmount;
+    params
+        .bots
+        .iter()
+        .enumerate()
+        .map(|(seat, bot)| {
+            let resources_taken = match bot.strategy {
+                BotStrategy::Sustainable => sustainable_share,
+                BotStrategy::Greedy => greedy_share,
+                BotStrategy::TitForTat => {
+                    let human_moves = &last_state.player_stats;
+                    if human_moves.is_empty() {
+                        sustainable_share
+                    } else {
+                        let taken: ResourceAmount = human_moves.values().sum();
+                        let average = taken / human_moves.len() as ResourceAmount;
+                        // the others may have taken more than is left now
+                        average.min(greedy_share)
+                    }
+                }
+                BotStrategy::Random => {
+                    let salt = last_round_num << 16 | seat as u32;
+                    let random = seeded_random(seed, salt);
+                    (random % (greedy_share as u64 + 1)) as ResourceAmount
+                }
+            };
+            (bot.name.clone(), resources_taken)
+        })
+        .collect()
+}
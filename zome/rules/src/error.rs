@@ -0,0 +1,10 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum RulesError {
+    #[error("Game needs between {min} and {max} players, but it has {actual}")]
+    WrongPlayerCount { min: u32, max: u32, actual: usize },
+
+    #[error("Invalid game params: {0}")]
+    InvalidGameParams(String),
+}
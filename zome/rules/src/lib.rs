@@ -0,0 +1,24 @@
+//! Rules of the tragedy of the commons game: how a round is calculated from the moves
+//! made in it, when the game ends and how bots play. Nothing here talks to Holochain,
+//! so the zome and the simulation share exactly the same rules.
+//!
+//! Players are identified by any ordered type, the zome uses AgentPubKey for them.
+
+mod bots;
+mod error;
+mod params;
+mod round;
+mod schedule;
+mod simulation;
+mod stats;
+
+pub use bots::{bot_moves, BotSeat, BotStats, BotStrategy};
+pub use error::RulesError;
+pub use params::{seeded_random, GameParams, RoundLength, MIN_PLAYERS};
+pub use round::{calculate_round_state, finalize_moves, start_new_round, GameOutcome, RoundState};
+pub use schedule::round_robin_schedule;
+pub use simulation::{simulate, simulate_with_seed, SimulatedGame};
+pub use stats::{
+    gini_coefficient, new_player_stats, player_stats_from_moves, sustainable_harvest,
+    team_stats_from_moves, Move, PlayerStats, ResourceAmount, TeamStats,
+};
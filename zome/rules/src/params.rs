@@ -0,0 +1,175 @@
+use crate::bots::BotSeat;
+use crate::error::RulesError;
+use crate::stats::ResourceAmount;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// How long the game lasts. Players who know which round is the last one are
+/// tempted to grab everything in it, so the length can be kept hidden from them
+#[derive(Clone, Debug, Serialize, Deserialize, Copy, PartialEq)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RoundLength {
+    /// Game lasts exactly num_rounds rounds
+    Fixed,
+    /// Game lasts between min_rounds and num_rounds rounds. The exact number is drawn
+    /// from the session hash and is only revealed in the results
+    Random { min_rounds: u32 },
+    /// After every round the game goes on with this probability, up to num_rounds rounds
+    Continuation { probability: f32 },
+}
+
+// not derived: #[default] on enum variants needs a newer rustc than the holonix one
+#[allow(clippy::derivable_impls)]
+impl Default for RoundLength {
+    fn default() -> Self {
+        RoundLength::Fixed
+    }
+}
+
+/// Fewest players, bots included, that a game can have
+pub const MIN_PLAYERS: u32 = 2;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct GameParams {
+    pub regeneration_factor: f32,
+    pub start_amount: ResourceAmount,
+    pub num_rounds: u32,
+    pub min_players: u32,
+    pub max_players: u32,
+    #[serde(default)]
+    pub round_length: RoundLength,
+    /// Seats played by bots, on top of the players who joined the game
+    #[serde(default)]
+    pub bots: Vec<BotSeat>,
+}
+
+impl Default for GameParams {
+    fn default() -> Self {
+        GameParams {
+            regeneration_factor: 1.1,
+            start_amount: 100,
+            num_rounds: 3,
+            min_players: MIN_PLAYERS,
+            max_players: 10,
+            round_length: RoundLength::Fixed,
+            bots: vec![],
+        }
+    }
+}
+
+impl GameParams {
+    /// Checks that the player limits make a game: it takes at least two to share a commons
+    pub fn check_player_limits(&self) -> Result<(), RulesError> {
+        if self.min_players < MIN_PLAYERS {
+            return Err(RulesError::InvalidGameParams(format!(
+                "min_players {} is less than {}",
+                self.min_players, MIN_PLAYERS
+            )));
+        }
+        if self.min_players > self.max_players {
+            return Err(RulesError::InvalidGameParams(format!(
+                "min_players {} is greater than max_players {}",
+                self.min_players, self.max_players
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks that the number of players, together with the bots, fits the limits of the params
+    pub fn check_player_count(&self, players: usize) -> Result<(), RulesError> {
+        self.check_player_limits()?;
+        let count = players + self.bots.len();
+        if count < self.min_players as usize || count > self.max_players as usize {
+            return Err(RulesError::WrongPlayerCount {
+                min: self.min_players,
+                max: self.max_players,
+                actual: count,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that the game can last at least one round and at most num_rounds
+    pub fn check_round_length(&self) -> Result<(), RulesError> {
+        if self.num_rounds == 0 {
+            return Err(RulesError::InvalidGameParams(String::from(
+                "game has to have at least one round",
+            )));
+        }
+        match self.round_length {
+            RoundLength::Random { min_rounds }
+                if min_rounds == 0 || min_rounds > self.num_rounds =>
+            {
+                Err(RulesError::InvalidGameParams(format!(
+                    "min_rounds {} has to be between 1 and num_rounds {}",
+                    min_rounds, self.num_rounds
+                )))
+            }
+            RoundLength::Continuation { probability } if !(0.0..=1.0).contains(&probability) => {
+                Err(RulesError::InvalidGameParams(format!(
+                    "continuation probability {} has to be between 0 and 1",
+                    probability
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that every bot has its own name
+    pub fn check_bots(&self) -> Result<(), RulesError> {
+        let mut names = BTreeSet::new();
+        for bot in self.bots.iter() {
+            if bot.name.trim().is_empty() {
+                return Err(RulesError::InvalidGameParams(String::from(
+                    "bot name can't be empty",
+                )));
+            }
+            if !names.insert(bot.name.clone()) {
+                return Err(RulesError::InvalidGameParams(format!(
+                    "bot name {} is used twice",
+                    bot.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of players who can join the lobby, since bots take some of the seats
+    pub fn human_seats(&self) -> u32 {
+        self.max_players.saturating_sub(self.bots.len() as u32)
+    }
+
+    /// Number of rounds the game with these params lasts. The zome seeds it with the entry
+    /// hash of the original session, so every player, as well as validation, gets the same number
+    pub fn game_length(&self, seed: &[u8]) -> u32 {
+        match self.round_length {
+            RoundLength::Fixed => self.num_rounds,
+            RoundLength::Random { min_rounds } => {
+                let range = (self.num_rounds - min_rounds + 1) as u64;
+                min_rounds + (seeded_random(seed, 0) % range) as u32
+            }
+            RoundLength::Continuation { probability } => {
+                let mut length = 1;
+                while length < self.num_rounds && seeded_fraction(seed, length) < probability {
+                    length += 1;
+                }
+                length
+            }
+        }
+    }
+}
+
+/// Deterministic pseudo-random number derived from the seed and the salt (FNV-1a)
+pub fn seeded_random(seed: &[u8], salt: u32) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in seed.iter().chain(salt.to_le_bytes().iter()) {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Deterministic pseudo-random number from 0 (inclusive) to 1 (exclusive)
+fn seeded_fraction(seed: &[u8], salt: u32) -> f32 {
+    ((seeded_random(seed, salt) >> 40) as f32) / ((1u64 << 24) as f32)
+}
@@ -0,0 +1,197 @@
+use crate::bots::{bot_moves, BotStats};
+use crate::params::GameParams;
+use crate::stats::{
+    player_stats_from_moves, team_stats_from_moves, Move, PlayerStats, ResourceAmount, TeamStats,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// State of the commons after the moves of a round
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(serialize = "P: Serialize", deserialize = "P: Deserialize<'de> + Ord"))]
+pub struct RoundState<P> {
+    pub resources_left: ResourceAmount,
+    pub resources_taken: ResourceAmount,
+    pub resources_grown: ResourceAmount,
+    pub player_stats: PlayerStats<P>,
+    #[serde(default)]
+    pub team_stats: TeamStats, // empty unless the game is played in teams
+    #[serde(default)]
+    pub bot_stats: BotStats, // resources taken by the bots of the game
+}
+
+impl<P: Ord> RoundState<P> {
+    /// Creates a new RoundState instance with the provided input
+    pub fn new(
+        resources_left: ResourceAmount,
+        resources_taken: ResourceAmount,
+        resources_grown: ResourceAmount,
+        player_stats: PlayerStats<P>,
+        team_stats: TeamStats,
+        bot_stats: BotStats,
+    ) -> RoundState<P> {
+        RoundState {
+            resources_left,
+            resources_taken,
+            resources_grown,
+            player_stats,
+            team_stats,
+            bot_stats,
+        }
+    }
+
+    /// Whether anything is left in the commons after the round. A game can run its
+    /// full length and still end with nothing left
+    pub fn commons_survived(&self) -> bool {
+        self.resources_left > 0
+    }
+
+    /// State of the untouched commons before the first round
+    pub fn start(start_amount: ResourceAmount) -> RoundState<P> {
+        RoundState::new(
+            start_amount,
+            0,
+            0,
+            PlayerStats::new(),
+            TeamStats::new(),
+            BotStats::new(),
+        )
+    }
+}
+
+/// How the game has ended
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum GameOutcome {
+    Lost,
+    Finished,
+}
+
+impl GameOutcome {
+    /// Decides how the game has ended based on the state of its last round
+    pub fn from_round_state<P>(round_state: &RoundState<P>) -> GameOutcome {
+        if round_state.resources_taken <= 0 {
+            GameOutcome::Lost
+        } else {
+            GameOutcome::Finished
+        }
+    }
+}
+
+/// Calculates the state of the commons after the round that follows the last one,
+/// from the moves of the players and the moves bots make on their own.
+/// NOTE: this fn is used both in validation and when creating game round entries
+/// so it has to be very lightweight and can not depend on anything but its input
+pub fn calculate_round_state<P: Ord>(
+    last_round_num: u32,
+    last_state: &RoundState<P>,
+    seed: &[u8],
+    params: &GameParams,
+    player_moves: Vec<Move<P>>,
+) -> RoundState<P> {
+    // bots move only once every player has, based on the previous round alone
+    let bot_stats = bot_moves(last_round_num, last_state, seed, params, player_moves.len());
+
+    // nobody takes more than is left: when the moves ask for more than that,
+    // everyone gets the same part of what they asked for, rounded down
+    let available = last_state.resources_left.max(0) as i64;
+    let asked: i64 = player_moves
+        .iter()
+        .map(|x| x.resources as i64)
+        .chain(bot_stats.values().map(|taken| *taken as i64))
+        .sum();
+    let ration = |taken: ResourceAmount| -> ResourceAmount {
+        if asked <= available {
+            taken
+        } else {
+            (taken as i64 * available / asked) as ResourceAmount
+        }
+    };
+    let player_moves: Vec<Move<P>> = player_moves
+        .into_iter()
+        .map(|m| Move {
+            resources: ration(m.resources),
+            ..m
+        })
+        .collect();
+    let bot_stats: BotStats = bot_stats
+        .into_iter()
+        .map(|(name, taken)| (name, ration(taken)))
+        .collect();
+
+    // resources
+    let consumed_resources_in_round: ResourceAmount = player_moves
+        .iter()
+        .map(|x| x.resources)
+        .chain(bot_stats.values().cloned())
+        .sum();
+    let resources_left = last_state.resources_left - consumed_resources_in_round;
+    let total_leftover_resource = (resources_left as f32 * params.regeneration_factor) as i32;
+    let grown_resources_in_round = total_leftover_resource - resources_left;
+
+    // player stats
+    let team_stats = team_stats_from_moves(&player_moves);
+    let player_stats = player_stats_from_moves(player_moves);
+
+    RoundState {
+        resources_left: total_leftover_resource,
+        resources_taken: consumed_resources_in_round,
+        resources_grown: grown_resources_in_round,
+        player_stats,
+        team_stats,
+        bot_stats,
+    }
+}
+
+/// Decides whether the game goes on after the round that follows the last one:
+/// there have to be rounds left to play and resources left in the commons
+pub fn start_new_round<P>(
+    params: &GameParams,
+    seed: &[u8],
+    last_round_num: u32,
+    round_state: &RoundState<P>,
+) -> bool {
+    // rounds left to play? when the length is hidden, this is the only place that knows it
+    last_round_num + 1 < params.game_length(seed)
+    // resources not depleted?
+        && round_state.resources_left > 0
+}
+
+/// Consumes list of moves passed to it to finalize them. Moves come with some unique
+/// key, the zome uses their entry hashes, and owner_of tells who made the move.
+/// If every player made at least one move, it returns list of moves which is guaranteed
+/// to have a single move for every player, ordered by player.
+/// If there are missing moves, it returns None, since we can't finalize the moves and
+/// have to wait for other players instead.
+/// When a player has several moves, the one with the lowest key is taken, so
+/// every agent closing the round picks exactly the same moves.
+pub fn finalize_moves<K: Ord, M, P: Ord>(
+    moves: Vec<(K, M)>,
+    number_of_players: usize,
+    owner_of: impl Fn(&M) -> P,
+) -> Option<Vec<(K, M)>> {
+    // Check that at least we have as many moves
+    // as there are players in the game
+    if moves.len() < number_of_players {
+        return None;
+    }
+    // Now that we know we have moves >= num of players, we need
+    // to make sure that every player made at least one move, so
+    // we're not closing the round without someone's move
+    let mut moves_per_player: BTreeMap<P, Vec<(K, M)>> = BTreeMap::new();
+    for (key, m) in moves {
+        moves_per_player
+            .entry(owner_of(&m))
+            .or_default()
+            .push((key, m));
+    }
+    if moves_per_player.len() < number_of_players {
+        return None;
+    }
+    let mut new_moves = vec![];
+    for (_, move_vec) in moves_per_player {
+        if let Some(first_move) = move_vec.into_iter().min_by(|a, b| a.0.cmp(&b.0)) {
+            new_moves.push(first_move);
+        }
+    }
+    Some(new_moves)
+}
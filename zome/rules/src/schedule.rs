@@ -0,0 +1,22 @@
+/// Splits the pool into pool_size / group_size groups for every stage. Players are laid
+/// out in rows as wide as the number of groups, and in every stage each row is shifted
+/// by its index times the stage number, so players who shared a group in one stage
+/// mostly end up in different groups in the next ones
+pub fn round_robin_schedule<P: Clone>(
+    players: &[P],
+    group_size: u32,
+    stages: u32,
+) -> Vec<Vec<Vec<P>>> {
+    let num_groups = (players.len() / group_size.max(1) as usize).max(1);
+    (0..stages as usize)
+        .map(|stage| {
+            let mut groups: Vec<Vec<P>> = vec![vec![]; num_groups];
+            for (index, player) in players.iter().enumerate() {
+                let row = index / num_groups;
+                let column = index % num_groups;
+                groups[(column + row * stage) % num_groups].push(player.clone());
+            }
+            groups
+        })
+        .collect()
+}
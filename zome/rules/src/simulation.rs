@@ -0,0 +1,77 @@
+use crate::bots::{BotSeat, BotStrategy};
+use crate::params::GameParams;
+use crate::round::{calculate_round_state, start_new_round, GameOutcome, RoundState};
+use crate::stats::{gini_coefficient, ResourceAmount};
+use serde::{Deserialize, Serialize};
+
+/// Outcome of a game played by bots from start to end
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SimulatedGame {
+    pub outcome: GameOutcome,
+    /// Whether anything was left in the commons when the game ended
+    pub commons_survived: bool,
+    pub rounds_played: u32,
+    /// Resources left in the commons at the start and after every round
+    pub resource_trajectory: Vec<ResourceAmount>,
+    /// Harvest of every seat over the whole game, in the order of the strategies
+    pub harvest_per_seat: Vec<ResourceAmount>,
+    pub total_harvest: ResourceAmount,
+    pub gini_coefficient: f32,
+}
+
+/// Plays a game where every seat is a bot with one of the strategies. The game lasts
+/// as long as the params say, but never more than the number of rounds given
+pub fn simulate(params: &GameParams, strategies: &[BotStrategy], rounds: u32) -> SimulatedGame {
+    simulate_with_seed(params, strategies, rounds, &[])
+}
+
+/// Same as simulate, with the seed that random bots and hidden game length are drawn
+/// from. In the zome this is the hash of the session, here it can be anything
+pub fn simulate_with_seed(
+    params: &GameParams,
+    strategies: &[BotStrategy],
+    rounds: u32,
+    seed: &[u8],
+) -> SimulatedGame {
+    let seats: Vec<BotSeat> = strategies
+        .iter()
+        .enumerate()
+        .map(|(index, strategy)| BotSeat {
+            name: format!("seat {}", index + 1),
+            strategy: *strategy,
+        })
+        .collect();
+    let params = GameParams {
+        bots: seats.clone(),
+        ..params.clone()
+    };
+
+    // games without players still need a type for them, bots don't care which one
+    let mut state: RoundState<u32> = RoundState::start(params.start_amount);
+    let mut resource_trajectory = vec![state.resources_left];
+    let mut harvest_per_seat = vec![0; seats.len()];
+    let mut round_num = 0;
+    while round_num < rounds {
+        let next_state = calculate_round_state(round_num, &state, seed, &params, vec![]);
+        for (seat, harvest) in seats.iter().zip(harvest_per_seat.iter_mut()) {
+            *harvest += next_state.bot_stats.get(&seat.name).cloned().unwrap_or(0);
+        }
+        resource_trajectory.push(next_state.resources_left);
+        let goes_on = start_new_round(&params, seed, round_num, &next_state);
+        round_num += 1;
+        state = next_state;
+        if !goes_on {
+            break;
+        }
+    }
+
+    SimulatedGame {
+        outcome: GameOutcome::from_round_state(&state),
+        commons_survived: state.commons_survived(),
+        rounds_played: round_num,
+        resource_trajectory,
+        total_harvest: harvest_per_seat.iter().sum(),
+        gini_coefficient: gini_coefficient(&harvest_per_seat),
+        harvest_per_seat,
+    }
+}
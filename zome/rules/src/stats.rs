@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub type ResourceAmount = i32;
+pub type PlayerStats<P> = BTreeMap<P, ResourceAmount>;
+
+/// Resources taken by every team, by team name
+pub type TeamStats = BTreeMap<String, ResourceAmount>;
+
+/// Move of a single player in a round: how much they took from the commons
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Move<P> {
+    pub owner: P,
+    pub resources: ResourceAmount,
+    /// Team the move counts for, if the game is played in teams
+    pub team: Option<String>,
+}
+
+/// Generates empty PlayerStats with 0 values for every player in players
+pub fn new_player_stats<P: Ord + Clone>(players: &[P]) -> PlayerStats<P> {
+    players.iter().map(|player| (player.clone(), 0)).collect()
+}
+
+/// Generates PlayerStats instance with the state from the input moves
+pub fn player_stats_from_moves<P: Ord>(moves: Vec<Move<P>>) -> PlayerStats<P> {
+    moves.into_iter().map(|m| (m.owner, m.resources)).collect()
+}
+
+/// Generates TeamStats with the resources taken by the members of every team
+/// in the input moves. Moves made outside of any team are left out
+pub fn team_stats_from_moves<P>(moves: &[Move<P>]) -> TeamStats {
+    let mut team_stats = TeamStats::new();
+    for m in moves {
+        if let Some(team) = &m.team {
+            *team_stats.entry(team.clone()).or_insert(0) += m.resources;
+        }
+    }
+    team_stats
+}
+
+/// Commons with amount R left after harvest grows back to R * r, so the group can
+/// take R * (1 - 1/r) every round without ever shrinking the commons
+pub fn sustainable_harvest(start_amount: ResourceAmount, regeneration_factor: f32) -> f32 {
+    if regeneration_factor <= 1.0 {
+        return 0.0;
+    }
+    start_amount as f32 * (1.0 - 1.0 / regeneration_factor)
+}
+
+/// Mean absolute difference of all the pairs of harvests, divided by twice the mean harvest
+pub fn gini_coefficient(harvests: &[ResourceAmount]) -> f32 {
+    let n = harvests.len() as f32;
+    let total: ResourceAmount = harvests.iter().sum();
+    if harvests.is_empty() || total <= 0 {
+        return 0.0;
+    }
+    let mut sum_of_differences: f32 = 0.0;
+    for a in harvests {
+        for b in harvests {
+            sum_of_differences += (a - b).abs() as f32;
+        }
+    }
+    let mean = total as f32 / n;
+    sum_of_differences / (2.0 * n * n * mean)
+}
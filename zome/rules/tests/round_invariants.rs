@@ -0,0 +1,204 @@
+//! Invariants of the round calculation that have to hold for any game, checked
+//! with randomly generated params and moves
+use proptest::prelude::*;
+use tragedy_of_commons_rules::{
+    calculate_round_state, finalize_moves, simulate_with_seed, start_new_round, BotSeat,
+    BotStrategy, GameParams, Move, RoundLength, RoundState,
+};
+
+fn strategy() -> impl Strategy<Value = BotStrategy> {
+    prop_oneof![
+        Just(BotStrategy::Sustainable),
+        Just(BotStrategy::Greedy),
+        Just(BotStrategy::TitForTat),
+        Just(BotStrategy::Random),
+    ]
+}
+
+fn round_length(num_rounds: u32) -> impl Strategy<Value = RoundLength> {
+    prop_oneof![
+        Just(RoundLength::Fixed),
+        (1..=num_rounds).prop_map(|min_rounds| RoundLength::Random { min_rounds }),
+        (0.0f32..=1.0).prop_map(|probability| RoundLength::Continuation { probability }),
+    ]
+}
+
+fn game_params() -> impl Strategy<Value = GameParams> {
+    (1.0f32..3.0, 0..1000, 1u32..20)
+        .prop_flat_map(|(regeneration_factor, start_amount, num_rounds)| {
+            (
+                Just(regeneration_factor),
+                Just(start_amount),
+                Just(num_rounds),
+                round_length(num_rounds),
+                prop::collection::vec(strategy(), 0..5),
+            )
+        })
+        .prop_map(
+            |(regeneration_factor, start_amount, num_rounds, round_length, strategies)| {
+                GameParams {
+                    regeneration_factor,
+                    start_amount,
+                    num_rounds,
+                    min_players: 2,
+                    max_players: 10,
+                    round_length,
+                    bots: strategies
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, strategy)| BotSeat {
+                            name: format!("bot {}", index),
+                            strategy,
+                        })
+                        .collect(),
+                }
+            },
+        )
+}
+
+/// Moves of players 0..n, taking up to max_take each
+fn player_moves(max_take: i32) -> impl Strategy<Value = Vec<Move<u32>>> {
+    prop::collection::vec(1..=max_take.max(1), 0..6).prop_map(|takes| {
+        takes
+            .into_iter()
+            .enumerate()
+            .map(|(player, resources)| Move {
+                owner: player as u32,
+                resources,
+                team: None,
+            })
+            .collect()
+    })
+}
+
+proptest! {
+    #[test]
+    fn resources_are_conserved(
+        params in game_params(),
+        moves in player_moves(200),
+        last_round_num in 0u32..20,
+        seed in prop::collection::vec(any::<u8>(), 0..39),
+    ) {
+        let last_state = RoundState::start(params.start_amount);
+        let state = calculate_round_state(last_round_num, &last_state, &seed, &params, moves);
+        prop_assert_eq!(
+            state.resources_left + state.resources_taken - state.resources_grown,
+            last_state.resources_left
+        );
+        let taken: i32 = state
+            .player_stats
+            .values()
+            .chain(state.bot_stats.values())
+            .sum();
+        prop_assert_eq!(taken, state.resources_taken);
+    }
+
+    #[test]
+    fn resources_never_go_negative(
+        params in game_params(),
+        resources_left in 0..1000,
+        last_takes in player_moves(5000),
+        moves in player_moves(5000),
+        last_round_num in 0u32..20,
+        seed in prop::collection::vec(any::<u8>(), 0..39),
+    ) {
+        // what the players took last round is what tit-for-tat bots copy
+        let last_state = RoundState::new(
+            resources_left,
+            0,
+            0,
+            last_takes.into_iter().map(|m| (m.owner, m.resources)).collect(),
+            Default::default(),
+            Default::default(),
+        );
+        // players may ask for far more than there is, they just can't get it
+        let state = calculate_round_state(last_round_num, &last_state, &seed, &params, moves);
+        prop_assert!(state.resources_taken <= resources_left);
+        prop_assert!(state.resources_left >= 0);
+        prop_assert!(state.resources_grown >= 0);
+        prop_assert!(state.player_stats.values().all(|taken| *taken >= 0));
+        prop_assert!(state.bot_stats.values().all(|taken| *taken >= 0));
+    }
+
+    #[test]
+    fn bots_never_take_more_than_there_is(
+        params in game_params(),
+        strategies in prop::collection::vec(strategy(), 1..8),
+        seed in prop::collection::vec(any::<u8>(), 0..39),
+    ) {
+        let game = simulate_with_seed(&params, &strategies, params.num_rounds, &seed);
+        for resources_left in game.resource_trajectory {
+            prop_assert!(resources_left >= 0, "commons went down to {}", resources_left);
+        }
+        for harvest in game.harvest_per_seat {
+            prop_assert!(harvest >= 0);
+        }
+    }
+
+    #[test]
+    fn finalized_moves_have_every_player_once(
+        moves in prop::collection::vec((any::<u32>(), 0u32..6, 1..100), 0..30),
+        number_of_players in 0usize..6,
+    ) {
+        let moves: Vec<(u32, Move<u32>)> = moves
+            .into_iter()
+            .map(|(key, owner, resources)| (key, Move { owner, resources, team: None }))
+            .collect();
+        let mut players: Vec<u32> = moves.iter().map(|(_, m)| m.owner).collect();
+        players.sort_unstable();
+        players.dedup();
+
+        match finalize_moves(moves.clone(), number_of_players, |m| m.owner) {
+            Some(finalized) => {
+                let owners: Vec<u32> = finalized.iter().map(|(_, m)| m.owner).collect();
+                prop_assert_eq!(&owners, &players);
+                // the move with the lowest key is the one every agent picks
+                for (key, m) in finalized.iter() {
+                    let lowest = moves
+                        .iter()
+                        .filter(|(_, other)| other.owner == m.owner)
+                        .map(|(key, _)| *key)
+                        .min();
+                    prop_assert_eq!(Some(*key), lowest);
+                }
+            }
+            None => prop_assert!(players.len() < number_of_players),
+        }
+    }
+
+    #[test]
+    fn no_new_round_after_num_rounds(
+        params in game_params(),
+        last_round_num in 0u32..40,
+        seed in prop::collection::vec(any::<u8>(), 0..39),
+    ) {
+        let state: RoundState<u32> = RoundState::start(params.start_amount);
+        let game_length = params.game_length(&seed);
+        prop_assert!(game_length >= 1 && game_length <= params.num_rounds);
+        if last_round_num + 1 >= params.num_rounds {
+            prop_assert!(!start_new_round(&params, &seed, last_round_num, &state));
+        }
+        prop_assert_eq!(
+            start_new_round(&params, &seed, last_round_num, &state),
+            last_round_num + 1 < game_length && state.resources_left > 0
+        );
+    }
+
+    #[test]
+    fn sustainable_bots_play_every_round(
+        num_rounds in 1u32..30,
+        seats in 1usize..6,
+        start_amount in 1..1000,
+        regeneration_factor in 1.05f32..3.0,
+    ) {
+        let params = GameParams {
+            num_rounds,
+            start_amount,
+            regeneration_factor,
+            ..GameParams::default()
+        };
+        let strategies = vec![BotStrategy::Sustainable; seats];
+        let game = simulate_with_seed(&params, &strategies, num_rounds + 10, &[]);
+        prop_assert_eq!(game.rounds_played, num_rounds);
+    }
+}
@@ -0,0 +1,64 @@
+//! Groups the round robin schedule splits the tournament players into
+use proptest::prelude::*;
+use tragedy_of_commons_rules::round_robin_schedule;
+
+#[test]
+fn players_change_groups_between_stages() {
+    let schedule = round_robin_schedule(&[1, 2, 3, 4], 2, 2);
+    assert_eq!(
+        schedule,
+        vec![vec![vec![1, 3], vec![2, 4]], vec![vec![1, 4], vec![2, 3]]]
+    );
+}
+
+#[test]
+fn leftover_players_join_existing_groups() {
+    let schedule = round_robin_schedule(&[1, 2, 3, 4, 5], 2, 1);
+    assert_eq!(schedule, vec![vec![vec![1, 3, 5], vec![2, 4]]]);
+}
+
+#[test]
+fn small_pool_plays_in_a_single_group() {
+    let schedule = round_robin_schedule(&[1, 2, 3], 4, 2);
+    assert_eq!(schedule, vec![vec![vec![1, 2, 3]], vec![vec![1, 2, 3]]]);
+}
+
+#[test]
+fn no_stages_no_groups() {
+    assert!(round_robin_schedule(&[1, 2, 3, 4], 2, 0).is_empty());
+}
+
+proptest! {
+    #[test]
+    fn every_player_plays_once_per_stage(
+        pool_size in 1u32..40,
+        group_size in 1u32..8,
+        stages in 0u32..6,
+    ) {
+        let players: Vec<u32> = (0..pool_size).collect();
+        let schedule = round_robin_schedule(&players, group_size, stages);
+        prop_assert_eq!(schedule.len(), stages as usize);
+        for groups in schedule {
+            let mut stage_players: Vec<u32> = groups.iter().flatten().cloned().collect();
+            stage_players.sort_unstable();
+            prop_assert_eq!(&stage_players, &players);
+        }
+    }
+
+    #[test]
+    fn groups_are_at_least_group_size(
+        pool_size in 1u32..40,
+        group_size in 1u32..8,
+        stages in 1u32..6,
+    ) {
+        let players: Vec<u32> = (0..pool_size).collect();
+        let num_groups = (pool_size / group_size).max(1) as usize;
+        for groups in round_robin_schedule(&players, group_size, stages) {
+            prop_assert_eq!(groups.len(), num_groups);
+            for group in groups {
+                prop_assert!(group.len() >= group_size.min(pool_size) as usize);
+                prop_assert!(group.len() <= pool_size as usize / num_groups + 1);
+            }
+        }
+    }
+}
@@ -18,4 +18,16 @@ const orchestrator = new Orchestrator();
 export const installation: InstallAgentsHapps = [
   [[dnaPath]],  // agent 0 - happ 0
   [[dnaPath]],  // agent 1 - happ 0
-];
\ No newline at end of file
+];
+// Every round has a designated closer, so we let all players try to close
+// the round until one of them actually closes it
+export const closeRound = async (players: InstalledHapp[], zomeName: string, roundHash) => {
+  let result;
+  for (const player of players) {
+    result = await player.cells[0].call(zomeName, "try_to_close_round", roundHash);
+    if (result.next_action.type != "WAITING_FOR_CLOSER") {
+      return result;
+    }
+  }
+  return result;
+};
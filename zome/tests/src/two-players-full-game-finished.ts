@@ -2,7 +2,7 @@ import {
     Orchestrator,
 } from "@holochain/tryorama";
 import { ScenarioApi } from "@holochain/tryorama/lib/api";
-import { conductorConfig, installation, sleep} from './common'
+import { closeRound, conductorConfig, installation, sleep} from './common'
 
 const orchestrator = new Orchestrator();
 
@@ -77,6 +77,22 @@ orchestrator.registerScenario(
     console.log("Bob joined game: ", game_code_anchor_entry_hash_bob);
     t.deepEqual(game_code_anchor_entry_hash, game_code_anchor_entry_hash_bob);
 
+    await sleep(2000); // wait until player links have propagated
+
+    // both players tell they're ready to play
+    let alice_ready = await alice_common.cells[0].call(
+      ZOME_NAME,
+      "set_player_ready",
+      {game_code: GAME_CODE, ready: true}
+    );
+    t.ok(alice_ready);
+    let bob_ready = await bob_common.cells[0].call(
+      ZOME_NAME,
+      "set_player_ready",
+      {game_code: GAME_CODE, ready: true}
+    );
+    t.ok(bob_ready);
+
     await sleep(5000); // wait until all links have propagated
 
     let list_of_players = await alice_common.cells[0].call(
@@ -92,7 +108,7 @@ orchestrator.registerScenario(
     let first_round_hash = await alice_common.cells[0].call(
       ZOME_NAME,
       "start_game_session_with_code",
-      GAME_CODE
+      {game_code: GAME_CODE, force: false}
     );
     prev_round_hash = first_round_hash;
     console.log("Game session started, first round hash: ", prev_round_hash);
@@ -127,15 +143,11 @@ orchestrator.registerScenario(
     await sleep(2000);
 
     // Check to close the first round
-    let close_game_round_1_bob = await bob_common.cells[0].call(
-      ZOME_NAME,
-      "try_to_close_round",
-      prev_round_hash,
-    );
+    let close_game_round_1_bob = await closeRound([bob_common, alice_common], ZOME_NAME, prev_round_hash);
     console.log("Bob tried to close round 1: ", close_game_round_1_bob);
-    console.log("Verify that first round has ended and next_action = START_NEXT_ROUND:", close_game_round_1_bob.next_action);
-    t.ok(close_game_round_1_bob.next_action == "START_NEXT_ROUND");
-    prev_round_hash = close_game_round_1_bob.current_round_entry_hash;
+    console.log("Verify that first round has ended and next_action = NEXT_ROUND:", close_game_round_1_bob.next_action.type);
+    t.ok(close_game_round_1_bob.next_action.type == "NEXT_ROUND");
+    prev_round_hash = close_game_round_1_bob.next_action.round_entry_hash;
 
     // wait for round data to propagate
     await sleep(2000);
@@ -165,18 +177,24 @@ orchestrator.registerScenario(
     await sleep(5000);
 
     // Check to close the round
-    let close_game_round_2_alice = await alice_common.cells[0].call(
-      ZOME_NAME,
-      "try_to_close_round",
-      prev_round_hash,
-    );
+    let close_game_round_2_alice = await closeRound([alice_common, bob_common], ZOME_NAME, prev_round_hash);
     console.log("Alice tried to close round 2: ", close_game_round_2_alice);
-    console.log("Verify that round 2 has ended and next_action = START_NEXT_ROUND: ", close_game_round_2_alice.next_action);
-    t.ok(close_game_round_2_alice.next_action == "START_NEXT_ROUND");
+    console.log("Verify that round 2 has ended and next_action = NEXT_ROUND: ", close_game_round_2_alice.next_action.type);
+    t.ok(close_game_round_2_alice.next_action.type == "NEXT_ROUND");
+    prev_round_hash = close_game_round_2_alice.next_action.round_entry_hash;
 
     // wait for round data to propagate
     await sleep(2000);
 
+    // Closing the same round again returns the round that already exists
+    let close_game_round_2_bob = await bob_common.cells[0].call(
+      ZOME_NAME,
+      "try_to_close_round",
+      close_game_round_2_alice.prev_round_entry_hash,
+    );
+    console.log("Bob tried to close round 2 again: ", close_game_round_2_bob);
+    t.deepEqual(close_game_round_2_bob.next_action.round_entry_hash, prev_round_hash);
+
     // ROUND 3
     // Alice makes her move
     let game_move_round_3_alice = await alice_common.cells[0].call(
@@ -198,14 +216,10 @@ orchestrator.registerScenario(
 
     await sleep(5000)
     // Check to close the round
-    let close_game_round_3_alice = await alice_common.cells[0].call(
-      ZOME_NAME,
-      "try_to_close_round",
-      prev_round_hash,
-    );
+    let close_game_round_3_alice = await closeRound([alice_common, bob_common], ZOME_NAME, prev_round_hash);
     console.log("Alice tried to close round 3: ", close_game_round_3_alice);
-    console.log("Verify that round 3 has ended and next_action = SHOW_GAME_RESULTS: ", );
-    t.ok(close_game_round_3_alice.next_action == "SHOW_GAME_RESULTS");
+    console.log("Verify that round 3 has ended and next_action = GAME_OVER: ", );
+    t.ok(close_game_round_3_alice.next_action.type == "GAME_OVER");
   }
 );
 
@@ -2,7 +2,7 @@ import {
     Orchestrator,
 } from "@holochain/tryorama";
 import { ScenarioApi } from "@holochain/tryorama/lib/api";
-import { conductorConfig, installation, sleep} from './common'
+import { closeRound, conductorConfig, installation, sleep} from './common'
 
 const orchestrator = new Orchestrator();
 
@@ -79,6 +79,22 @@ orchestrator.registerScenario(
     console.log("Bob joined game: ", game_code_anchor_entry_hash_bob);
     t.deepEqual(game_code_anchor_entry_hash, game_code_anchor_entry_hash_bob);
 
+    await sleep(2000); // wait until player links have propagated
+
+    // both players tell they're ready to play
+    let alice_ready = await alice_common.cells[0].call(
+      ZOME_NAME,
+      "set_player_ready",
+      {game_code: GAME_CODE, ready: true}
+    );
+    t.ok(alice_ready);
+    let bob_ready = await bob_common.cells[0].call(
+      ZOME_NAME,
+      "set_player_ready",
+      {game_code: GAME_CODE, ready: true}
+    );
+    t.ok(bob_ready);
+
     await sleep(5000); // wait until all links have propagated
 
     let list_of_players = await alice_common.cells[0].call(
@@ -94,7 +110,7 @@ orchestrator.registerScenario(
     let first_round_hash = await alice_common.cells[0].call(
       ZOME_NAME,
       "start_game_session_with_code",
-      GAME_CODE
+      {game_code: GAME_CODE, force: false}
     );
     prev_round_hash = first_round_hash;
     console.log("Game session started, first round hash: ", prev_round_hash);
@@ -156,14 +172,10 @@ orchestrator.registerScenario(
     t.ok(bob_owned_games.length == 0);
 
     // CHECK  TO CLOSE GAME
-    let close_game_round_1_bob = await bob_common.cells[0].call(
-      ZOME_NAME,
-      "try_to_close_round",
-      prev_round_hash,
-    );
+    let close_game_round_1_bob = await closeRound([bob_common, alice_common], ZOME_NAME, prev_round_hash);
     console.log("Bob tried to close round 1: ", close_game_round_1_bob);
-    console.log("Verify that game has ended and next_action = SHOW_GAME_RESULTS");
-    t.ok(close_game_round_1_bob.next_action == "SHOW_GAME_RESULTS");
+    console.log("Verify that game has ended and next_action = GAME_OVER");
+    t.ok(close_game_round_1_bob.next_action.type == "GAME_OVER");
   }
 );
 
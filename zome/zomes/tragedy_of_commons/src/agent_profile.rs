@@ -0,0 +1,152 @@
+use crate::player_profile::{check_nickname, check_nickname_unique, get_games_of_agent};
+use crate::utils::{
+    entry_from_element_create_or_update, get_latest_entry, must_get_entry_struct,
+    update_entry_if_changed,
+};
+use hdk::prelude::*;
+
+pub const AGENT_PROFILE_LINK_TAG: &str = "PROFILE";
+
+/// Holochain entry that stores user's profile independently of any game.
+/// It is linked from the agent's pub key and is reused by every game the agent joins
+#[hdk_entry(id = "agent_profile", visibility = "public")]
+#[derive(Clone)]
+pub struct AgentProfile {
+    pub agent: AgentPubKey,
+    pub nickname: String,
+    pub avatar_color: String,
+    pub bio: String,
+}
+
+/// Struct to receive user input from the UI when user
+/// wants to create or change their profile
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct AgentProfileInput {
+    pub nickname: String,
+    pub avatar_color: String,
+    pub bio: String,
+}
+
+/// Creates the profile of the calling agent, or updates it if it already exists
+pub fn set_my_profile(input: AgentProfileInput) -> ExternResult<EntryHash> {
+    check_nickname(&input.nickname)?;
+    let agent = agent_info()?.agent_initial_pubkey;
+    let profile = AgentProfile {
+        agent: agent.clone(),
+        nickname: input.nickname,
+        avatar_color: input.avatar_color,
+        bio: input.bio,
+    };
+    match get_agent_profile_with_header(agent.clone())? {
+        Some((header_hash, prev_profile)) => {
+            if prev_profile.nickname != profile.nickname {
+                // the nickname is shown in every game the agent has joined,
+                // so it has to be free in all of them
+                for anchor in get_games_of_agent(agent.clone())? {
+                    check_nickname_unique(anchor, &profile.nickname)?;
+                }
+            }
+            debug!("set_my_profile | updating profile {:#?}", profile);
+            update_entry_if_changed(header_hash, &prev_profile, &profile)?;
+        }
+        None => {
+            debug!("set_my_profile | creating profile {:#?}", profile);
+            create_entry(&profile)?;
+            create_link(
+                agent.into(),
+                hash_entry(&profile)?,
+                LinkTag::new(String::from(AGENT_PROFILE_LINK_TAG)),
+            )?;
+        }
+    }
+    hash_entry(&profile)
+}
+
+/// Retrieves the latest version of the agent's profile, if the agent has one
+pub fn get_agent_profile(agent: AgentPubKey) -> ExternResult<Option<AgentProfile>> {
+    Ok(get_agent_profile_with_header(agent)?.map(|(_, profile)| profile))
+}
+
+/// Retrieves the latest version of the agent's profile together with header hash of this version.
+/// Profiles that belong to someone else are skipped, even if they are linked from the agent
+fn get_agent_profile_with_header(
+    agent: AgentPubKey,
+) -> ExternResult<Option<(HeaderHash, AgentProfile)>> {
+    let links = get_links(
+        agent.clone().into(),
+        Some(LinkTag::new(String::from(AGENT_PROFILE_LINK_TAG))),
+    )?;
+    let mut links = links.into_inner();
+    links.sort_by_key(|link| link.timestamp.clone());
+    for link in links {
+        let (_, header_hash, profile) = get_latest_entry::<AgentProfile>(link.target)?;
+        if profile.agent == agent {
+            return Ok(Some((header_hash, profile)));
+        }
+    }
+    Ok(None)
+}
+
+pub fn validate_create_entry_agent_profile(
+    data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    let profile: AgentProfile = entry_from_element_create_or_update(&data.element)?;
+    if &profile.agent != data.element.header().author() {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "AgentProfile can only be created by the agent it belongs to",
+        )));
+    }
+    if let Err(e) = check_nickname(&profile.nickname) {
+        return e.into();
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_update_entry_agent_profile(
+    data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    let profile: AgentProfile = entry_from_element_create_or_update(&data.element)?;
+    let prev_profile = match data.element.header() {
+        Header::Update(update) => {
+            must_get_entry_struct::<AgentProfile>(update.original_entry_address.clone())?
+        }
+        _ => {
+            return Ok(ValidateCallbackResult::Invalid(String::from(
+                "AgentProfile's element has the wrong header: expected Update",
+            )))
+        }
+    };
+    if &prev_profile.agent != data.element.header().author() || prev_profile.agent != profile.agent
+    {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "AgentProfile can only be updated by the agent it belongs to",
+        )));
+    }
+    if let Err(e) = check_nickname(&profile.nickname) {
+        return e.into();
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/// Validates PROFILE link: agents can only link their own profile from their own key
+pub fn validate_create_link_agent_profile(
+    data: &ValidateCreateLinkData,
+) -> ExternResult<ValidateLinkCallbackResult> {
+    let author: EntryHash = data.link_add.author.clone().into();
+    if data.link_add.base_address != author {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "Agents can only link a profile from their own key",
+        )));
+    }
+    match AgentProfile::try_from(data.target.clone()) {
+        Ok(profile) if profile.agent == data.link_add.author => {
+            Ok(ValidateLinkCallbackResult::Valid)
+        }
+        Ok(_) => Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "Agents can only link their own AgentProfile",
+        ))),
+        Err(_) => Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "PROFILE link has to target an AgentProfile entry",
+        ))),
+    }
+}
@@ -1,5 +1,6 @@
 use hdk::prelude::*;
 use thiserror::Error;
+use tragedy_of_commons_rules::RulesError;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -12,13 +13,136 @@ pub enum Error {
     #[error("Element is missing Entry hash")]
     EntryHashMissing,
 
+    #[error("Game not found")]
+    GameNotFound,
+
+    #[error("Round not found")]
+    RoundNotFound,
+
+    #[error("Game move not found")]
+    MoveNotFound,
+
+    #[error("Lobby not found")]
+    LobbyNotFound,
+
+    #[error("This player is not known in the game session. Probably joined the game too late.")]
+    NotAPlayer,
+
+    #[error("Only the host of the lobby can do this")]
+    NotTheHost,
+
+    #[error("Round is already closed")]
+    RoundClosed,
+
+    #[error("Player has already made a move in this round")]
+    DuplicateMove,
+
+    #[error("Lobby is full: game can have at most {0} players")]
+    LobbyFull(u32),
+
+    #[error("Game needs between {min} and {max} players, but it has {actual}")]
+    WrongPlayerCount { min: u32, max: u32, actual: usize },
+
+    #[error("Invalid game params: {0}")]
+    InvalidGameParams(String),
+
+    #[error("Not all players are ready to start the game")]
+    PlayersNotReady,
+
+    #[error("Game has already been started")]
+    GameAlreadyStarted,
+
+    #[error("Game is still in progress")]
+    GameInProgress,
+
+    #[error("Rematch of this game has already been started")]
+    RematchAlreadyStarted,
+
+    #[error("Invalid tournament: {0}")]
+    InvalidTournament(String),
+
+    #[error("Invalid teams: {0}")]
+    InvalidTeams(String),
+
+    #[error("Team {team} can take at most {quota} resources per round")]
+    TeamQuotaExceeded { team: String, quota: i32 },
+
+    #[error("Only {0} resources are left in the commons")]
+    NotEnoughResources(i32),
+
+    #[error("Invalid nickname: {0}")]
+    InvalidNickname(String),
+
+    #[error("Nickname {0} is already taken in this game")]
+    NicknameTaken(String),
+
     #[error("Wasm Error {0}")]
     Wasm(WasmError),
 }
 
+impl Error {
+    /// Machine-readable code of the error, which the UI can rely on
+    /// instead of matching the error message
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::WrongHeader => "WRONG_HEADER",
+            Error::EntryMissing => "ENTRY_MISSING",
+            Error::EntryHashMissing => "ENTRY_HASH_MISSING",
+            Error::GameNotFound => "GAME_NOT_FOUND",
+            Error::RoundNotFound => "ROUND_NOT_FOUND",
+            Error::MoveNotFound => "MOVE_NOT_FOUND",
+            Error::LobbyNotFound => "LOBBY_NOT_FOUND",
+            Error::NotAPlayer => "NOT_A_PLAYER",
+            Error::NotTheHost => "NOT_THE_HOST",
+            Error::RoundClosed => "ROUND_CLOSED",
+            Error::DuplicateMove => "DUPLICATE_MOVE",
+            Error::LobbyFull(_) => "LOBBY_FULL",
+            Error::WrongPlayerCount { .. } => "WRONG_PLAYER_COUNT",
+            Error::InvalidGameParams(_) => "INVALID_GAME_PARAMS",
+            Error::PlayersNotReady => "PLAYERS_NOT_READY",
+            Error::GameAlreadyStarted => "GAME_ALREADY_STARTED",
+            Error::GameInProgress => "GAME_IN_PROGRESS",
+            Error::RematchAlreadyStarted => "REMATCH_ALREADY_STARTED",
+            Error::InvalidTournament(_) => "INVALID_TOURNAMENT",
+            Error::InvalidTeams(_) => "INVALID_TEAMS",
+            Error::TeamQuotaExceeded { .. } => "TEAM_QUOTA_EXCEEDED",
+            Error::NotEnoughResources(_) => "NOT_ENOUGH_RESOURCES",
+            Error::InvalidNickname(_) => "INVALID_NICKNAME",
+            Error::NicknameTaken(_) => "NICKNAME_TAKEN",
+            Error::Wasm(_) => "WASM_ERROR",
+        }
+    }
+}
+
+/// What the client receives as the WasmError::Guest payload, serialized to JSON,
+/// e.g. {"code":"NOT_A_PLAYER","message":"This player is not known..."}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+}
+
 impl From<Error> for WasmError {
     fn from(e: Error) -> Self {
-        WasmError::Guest(e.to_string())
+        let response = ErrorResponse {
+            code: e.code().into(),
+            message: e.to_string(),
+        };
+        match serde_json::to_string(&response) {
+            Ok(json) => WasmError::Guest(json),
+            Err(_) => WasmError::Guest(e.to_string()),
+        }
+    }
+}
+
+impl From<RulesError> for Error {
+    fn from(e: RulesError) -> Self {
+        match e {
+            RulesError::WrongPlayerCount { min, max, actual } => {
+                Error::WrongPlayerCount { min, max, actual }
+            }
+            RulesError::InvalidGameParams(reason) => Error::InvalidGameParams(reason),
+        }
     }
 }
 
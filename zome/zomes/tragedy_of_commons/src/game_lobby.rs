@@ -0,0 +1,409 @@
+use crate::{
+    error::Error,
+    game_code::{calculate_game_code_anchor_entry_hash, create_game_code_anchor, GAME_CODES_ANCHOR},
+    game_session::{GameParams, GameSignal, GAME_CODE_TO_SESSION_TAG},
+    player_profile::get_player_profiles_for_anchor,
+    utils::{
+        entry_from_element_create_or_update, get_latest_entry, must_get_entry_struct,
+        update_entry_if_changed,
+    },
+};
+use hdk::prelude::holo_hash::hash_type;
+use hdk::prelude::*;
+
+pub const LOBBY_LINK_TAG: &str = "LOBBY";
+pub const READY_LINK_TAG: &str = "READY";
+/// How long the lobby has to stay unchanged before any player can take over as a host
+pub const HOST_TAKEOVER_TIMEOUT_MICROS: i64 = 5 * 60 * 1_000_000;
+
+/// Holochain entry that stores the settings of a game lobby,
+/// i.e. everything we know about the game before its session is started.
+/// It is linked from the game code anchor and is updated by the host
+#[hdk_entry(id = "game_lobby", visibility = "public")]
+#[derive(Clone)]
+pub struct GameLobby {
+    pub game_code: String,
+    pub host: AgentPubKey,
+    pub public: bool,
+    pub game_params: GameParams,
+    /// Group (e.g. a class) whose leaderboard the games of this lobby are posted to
+    pub group: Option<String>,
+}
+
+/// Struct to receive user input from the UI when host
+/// wants to make their lobby (in)visible in the list of open games
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct LobbyVisibilityInput {
+    pub game_code: String,
+    pub public: bool,
+}
+
+/// Struct to receive user input from the UI when host wants the games
+/// of their lobby to count for a group leaderboard
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct LobbyGroupInput {
+    pub game_code: String,
+    pub group: Option<String>,
+}
+
+/// Struct to receive user input from the UI when host configures the game
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct LobbyGameParamsInput {
+    pub game_code: String,
+    pub game_params: GameParams,
+}
+
+/// Struct to receive user input from the UI when the host role
+/// should be passed to another player of the lobby
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct TransferHostInput {
+    pub game_code: String,
+    pub new_host: AgentPubKey,
+}
+
+/// Struct to receive user input from the UI when player
+/// signals that they're ready (or not ready anymore) to start the game
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct PlayerReadyInput {
+    pub game_code: String,
+    pub ready: bool,
+}
+
+/// Payload of the signal that is sent when a player changes their ready flag
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct PlayerReadyPayload {
+    pub game_code: String,
+    pub player: AgentPubKey,
+    pub ready: bool,
+}
+
+/// Info about a lobby that is sent to the UI when listing open games
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct OpenGameInfo {
+    pub game_code: String,
+    pub host_nickname: Option<String>,
+    pub player_count: usize,
+    pub game_params: GameParams,
+    pub started: bool,
+}
+
+/// Creates anchor for the game code and a GameLobby hosted by the caller.
+/// If the lobby for this game code already exists, we only make sure the
+/// anchor is available on our source chain and keep the lobby as is.
+pub fn create_lobby(game_code: String) -> ExternResult<EntryHash> {
+    let anchor = create_game_code_anchor(game_code.clone())?;
+    if get_lobby_for_anchor(anchor.clone())?.is_some() {
+        debug!("create_lobby | lobby for {} already exists", game_code);
+        return Ok(anchor);
+    }
+    let lobby = GameLobby {
+        game_code,
+        host: agent_info()?.agent_initial_pubkey,
+        public: false,
+        game_params: GameParams::default(),
+        group: None,
+    };
+    create_entry(&lobby)?;
+    let lobby_entry_hash = hash_entry(&lobby)?;
+    create_link(
+        anchor.clone(),
+        lobby_entry_hash,
+        LinkTag::new(String::from(LOBBY_LINK_TAG)),
+    )?;
+    debug!("create_lobby | lobby created {:#?}", lobby);
+    Ok(anchor)
+}
+
+/// Retrieves the latest version of the lobby linked from the game code anchor.
+/// Returns header hash of the latest lobby version together with the lobby itself.
+/// Validation lets only one lobby be created for a code, but in case two hosts
+/// raced to create one, the earliest one wins.
+pub fn get_lobby_for_anchor(anchor: EntryHash) -> ExternResult<Option<(HeaderHash, GameLobby)>> {
+    let links = get_links(anchor, Some(LinkTag::new(String::from(LOBBY_LINK_TAG))))?;
+    let first_link = links
+        .into_inner()
+        .into_iter()
+        .min_by_key(|link| link.timestamp.clone());
+    match first_link {
+        Some(link) => {
+            let (_, header_hash, lobby) = get_latest_entry::<GameLobby>(link.target)?;
+            Ok(Some((header_hash, lobby)))
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn get_lobby_for_game_code(game_code: String) -> ExternResult<Option<(HeaderHash, GameLobby)>> {
+    let anchor = calculate_game_code_anchor_entry_hash(game_code)?;
+    get_lobby_for_anchor(anchor)
+}
+
+/// Marks the lobby as public or private. Only public lobbies are listed in list_open_games
+pub fn set_lobby_visibility(input: LobbyVisibilityInput) -> ExternResult<EntryHash> {
+    let (header_hash, lobby) = match get_lobby_for_game_code(input.game_code)? {
+        Some(found) => found,
+        None => return Err(Error::LobbyNotFound.into()),
+    };
+    if lobby.host != agent_info()?.agent_initial_pubkey {
+        return Err(Error::NotTheHost.into());
+    }
+    let lobby_update = GameLobby {
+        public: input.public,
+        ..lobby.clone()
+    };
+    update_entry_if_changed(header_hash, &lobby, &lobby_update)
+}
+
+/// Sets the group of the lobby, which decides to which group leaderboard
+/// the games of this lobby are posted. Only the host can change it
+pub fn set_lobby_group(input: LobbyGroupInput) -> ExternResult<EntryHash> {
+    let (header_hash, lobby) = match get_lobby_for_game_code(input.game_code)? {
+        Some(found) => found,
+        None => return Err(Error::LobbyNotFound.into()),
+    };
+    if lobby.host != agent_info()?.agent_initial_pubkey {
+        return Err(Error::NotTheHost.into());
+    }
+    let lobby_update = GameLobby {
+        group: input.group,
+        ..lobby.clone()
+    };
+    update_entry_if_changed(header_hash, &lobby, &lobby_update)
+}
+
+/// Changes params of the game that will be started from the lobby. Only the host can do it
+pub fn set_lobby_game_params(input: LobbyGameParamsInput) -> ExternResult<EntryHash> {
+    let (header_hash, lobby) = match get_lobby_for_game_code(input.game_code)? {
+        Some(found) => found,
+        None => return Err(Error::LobbyNotFound.into()),
+    };
+    if lobby.host != agent_info()?.agent_initial_pubkey {
+        return Err(Error::NotTheHost.into());
+    }
+    input.game_params.check_round_length().map_err(Error::from)?;
+    input.game_params.check_bots().map_err(Error::from)?;
+    input.game_params.check_player_limits().map_err(Error::from)?;
+    let lobby_update = GameLobby {
+        game_params: input.game_params,
+        ..lobby.clone()
+    };
+    update_entry_if_changed(header_hash, &lobby, &lobby_update)
+}
+
+/// Makes new_host the host of the lobby. The current host can pass the role to any
+/// player of the lobby. In case the host disappeared, any player can take the role
+/// over themselves once the lobby wasn't changed for HOST_TAKEOVER_TIMEOUT_MICROS,
+/// which is checked by the validation of the lobby update.
+pub fn transfer_host(input: TransferHostInput) -> ExternResult<EntryHash> {
+    let anchor = calculate_game_code_anchor_entry_hash(input.game_code)?;
+    let (header_hash, lobby) = match get_lobby_for_anchor(anchor.clone())? {
+        Some(found) => found,
+        None => return Err(Error::LobbyNotFound.into()),
+    };
+    let me = agent_info()?.agent_initial_pubkey;
+    if lobby.host != me && input.new_host != me {
+        return Err(Error::NotTheHost.into());
+    }
+    let players = get_player_profiles_for_anchor(anchor)?;
+    if !players.iter().any(|p| p.player_id == input.new_host) {
+        return Err(Error::NotAPlayer.into());
+    }
+    let lobby_update = GameLobby {
+        host: input.new_host,
+        ..lobby.clone()
+    };
+    update_entry_if_changed(header_hash, &lobby, &lobby_update)
+}
+
+/// Sets the ready flag of the calling agent in the lobby. Ready flag is stored
+/// as a READY link from the game code anchor to the agent's pub key, so
+/// toggling it off deletes this link. Notifies lobby players with a signal.
+pub fn set_player_ready(input: PlayerReadyInput) -> ExternResult<bool> {
+    let anchor = calculate_game_code_anchor_entry_hash(input.game_code.clone())?;
+    let me = agent_info()?.agent_initial_pubkey;
+    let players = get_player_profiles_for_anchor(anchor.clone())?;
+    if !players.iter().any(|p| p.player_id == me) {
+        return Err(Error::NotAPlayer.into());
+    }
+    let my_entry_hash: EntryHash = me.clone().into();
+    let my_ready_links: Vec<Link> = get_links(
+        anchor.clone(),
+        Some(LinkTag::new(String::from(READY_LINK_TAG))),
+    )?
+    .into_inner()
+    .into_iter()
+    .filter(|link| link.target == my_entry_hash)
+    .collect();
+
+    if input.ready && my_ready_links.is_empty() {
+        create_link(
+            anchor,
+            my_entry_hash,
+            LinkTag::new(String::from(READY_LINK_TAG)),
+        )?;
+    } else if !input.ready {
+        for link in my_ready_links {
+            delete_link(link.create_link_hash)?;
+        }
+    }
+
+    let signal = ExternIO::encode(GameSignal::PlayerReady(PlayerReadyPayload {
+        game_code: input.game_code,
+        player: me,
+        ready: input.ready,
+    }))?;
+    let player_keys: Vec<AgentPubKey> = players.into_iter().map(|p| p.player_id).collect();
+    remote_signal(signal, player_keys)?;
+    Ok(input.ready)
+}
+
+/// Retrieves pub keys of all the players that marked themselves as ready in the lobby
+pub fn get_ready_players(anchor: EntryHash) -> ExternResult<Vec<AgentPubKey>> {
+    let links = get_links(anchor, Some(LinkTag::new(String::from(READY_LINK_TAG))))?;
+    Ok(links
+        .into_inner()
+        .into_iter()
+        .map(|link| link.target.retype(hash_type::Agent))
+        .collect())
+}
+
+/// Walks all the game code anchors and collects info about every public lobby
+pub fn list_open_games() -> ExternResult<Vec<OpenGameInfo>> {
+    let mut open_games = vec![];
+    for anchor in list_anchor_addresses(GAME_CODES_ANCHOR.into())? {
+        let lobby = match get_lobby_for_anchor(anchor.clone())? {
+            Some((_, lobby)) if lobby.public => lobby,
+            _ => continue,
+        };
+        let players = get_player_profiles_for_anchor(anchor.clone())?;
+        let host_nickname = players
+            .iter()
+            .find(|p| p.player_id == lobby.host)
+            .map(|p| p.nickname.clone());
+        let session_links = get_links(
+            anchor,
+            Some(LinkTag::new(String::from(GAME_CODE_TO_SESSION_TAG))),
+        )?;
+        open_games.push(OpenGameInfo {
+            game_code: lobby.game_code,
+            host_nickname,
+            player_count: players.len(),
+            game_params: lobby.game_params,
+            started: !session_links.into_inner().is_empty(),
+        });
+    }
+    Ok(open_games)
+}
+
+pub fn validate_create_entry_game_lobby(
+    data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    let lobby: GameLobby = entry_from_element_create_or_update(&data.element)?;
+    if &lobby.host != data.element.header().author() {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "GameLobby can only be created with its author as a host",
+        )));
+    }
+    // game code has a single lobby, otherwise anyone could create another one naming
+    // themselves the host and start the game from it
+    let lobby_hash = hash_entry(&lobby)?;
+    let anchor = calculate_game_code_anchor_entry_hash(lobby.game_code.clone())?;
+    let links = get_links(anchor, Some(LinkTag::new(String::from(LOBBY_LINK_TAG))))?;
+    if links.into_inner().iter().any(|link| link.target != lobby_hash) {
+        return Ok(ValidateCallbackResult::Invalid(format!(
+            "Game code {} already has a GameLobby",
+            lobby.game_code
+        )));
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_update_entry_game_lobby(
+    data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    let lobby: GameLobby = entry_from_element_create_or_update(&data.element)?;
+    let update = match data.element.header() {
+        Header::Update(update) => update,
+        _ => {
+            return Ok(ValidateCallbackResult::Invalid(String::from(
+                "GameLobby's element has the wrong header: expected Update",
+            )))
+        }
+    };
+    let prev_header = must_get_header(update.original_header_address.clone())?;
+    let prev_lobby = must_get_entry_struct::<GameLobby>(update.original_entry_address.clone())?;
+    if prev_lobby.game_code != lobby.game_code {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "Can't change the game code of the GameLobby",
+        )));
+    }
+    if update.author == prev_lobby.host {
+        return Ok(ValidateCallbackResult::Valid);
+    }
+    // someone who isn't a host can only take the host role over after the timeout
+    let idle_micros =
+        update.timestamp.as_micros() - prev_header.header().timestamp().as_micros();
+    let is_takeover = lobby.host == update.author
+        && lobby.public == prev_lobby.public
+        && lobby.game_params == prev_lobby.game_params
+        && lobby.group == prev_lobby.group
+        && idle_micros >= HOST_TAKEOVER_TIMEOUT_MICROS;
+    if !is_takeover {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "Only the host can update the GameLobby",
+        )));
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/// Validates LOBBY link: only the host can link their lobby, and only from the anchor
+/// of its game code
+pub fn validate_create_link_lobby(
+    data: &ValidateCreateLinkData,
+) -> ExternResult<ValidateLinkCallbackResult> {
+    let lobby = match GameLobby::try_from(data.target.clone()) {
+        Ok(lobby) => lobby,
+        Err(_) => {
+            return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+                "LOBBY link has to target a GameLobby entry",
+            )))
+        }
+    };
+    if calculate_game_code_anchor_entry_hash(lobby.game_code.clone())? != data.link_add.base_address
+    {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "LOBBY link has to start from the anchor of the GameLobby's game code",
+        )));
+    }
+    if data.link_add.author != lobby.host {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "Only the host can link the GameLobby",
+        )));
+    }
+    Ok(ValidateLinkCallbackResult::Valid)
+}
+
+/// Validates READY link: players can only mark themselves as ready,
+/// and only in the lobby of a game they have joined
+pub fn validate_create_link_ready(
+    data: &ValidateCreateLinkData,
+) -> ExternResult<ValidateLinkCallbackResult> {
+    let author: EntryHash = data.link_add.author.clone().into();
+    if data.link_add.target_address != author {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "Players can only change their own ready flag",
+        )));
+    }
+    if get_lobby_for_anchor(data.link_add.base_address.clone())?.is_none() {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "READY link has to start from the game code anchor of a GameLobby",
+        )));
+    }
+    let players = get_player_profiles_for_anchor(data.link_add.base_address.clone())?;
+    if !players.iter().any(|p| p.player_id == data.link_add.author) {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "Only players who joined the game can mark themselves as ready",
+        )));
+    }
+    Ok(ValidateLinkCallbackResult::Valid)
+}
@@ -1,7 +1,7 @@
-use crate::{game_round::{calculate_round_state, GameRound, RoundState}, game_session::{GameScores, GameSession, GameSignal, SignalPayload}, types::ResourceAmount, utils::{check_agent_is_player_current_session, convert, convert_keys_from_b64, entry_from_element_create_or_update, entry_hash_from_element, must_get_entry_struct, try_get_and_convert}};
+use crate::{error::Error, game_round::{calculate_round_state, GameRound, RoundState}, game_session::{GameScores, GameSession, GameSignal, SessionState, SignalPayload, Team}, types::ResourceAmount, utils::{check_agent_is_player_current_session, convert, convert_keys_from_b64, entry_from_element_create_or_update, entry_hash_from_element, must_get_entry_struct, try_get_and_convert}};
 use hdk::prelude::holo_hash::hash_type::Agent;
 use hdk::prelude::*;
-use std::collections::BTreeMap;
+use tragedy_of_commons_rules::Move;
 
 pub const GAME_MOVE_LINK_TAG: &str = "GAME_MOVE";
 
@@ -11,7 +11,20 @@ pub struct GameMove {
     pub owner: AgentPubKey,
     pub round: EntryHash,
     pub resources: ResourceAmount,
+    #[serde(default)]
+    pub team: Option<String>, // team the move counts for, if the session is played in teams
 }
+impl GameMove {
+    /// The move as the game rules see it
+    pub fn to_move(&self) -> Move<AgentPubKey> {
+        Move {
+            owner: self.owner.clone(),
+            resources: self.resources,
+            team: self.team.clone(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
 pub struct GameMoveInput {
     pub resource_amount: ResourceAmount,
@@ -26,7 +39,7 @@ pub fn new_move(
     // round
     let game_round_element = match get(round_entry_hash.clone(), GetOptions::content())? {
         Some(element) => element,
-        None => return Err(WasmError::Guest("Round not found".into())),
+        None => return Err(Error::RoundNotFound.into()),
     };
     let entry_hash_game_round = entry_hash_from_element(&game_round_element)?.to_owned();
 
@@ -39,7 +52,7 @@ pub fn new_move(
     // game session
     let game_session_element = match get(game_round.session.clone(), GetOptions::content())? {
         Some(element) => element,
-        None => return Err(WasmError::Guest("Round not found".into())),
+        None => return Err(Error::GameNotFound.into()),
     };
     let game_session: GameSession = game_session_element
         .entry()
@@ -47,7 +60,42 @@ pub fn new_move(
         .to_owned()
         .expect("game session should be known");
 
-    check_agent_is_player_current_session(game_session);
+    if game_session.status != SessionState::InProgress {
+        return Err(Error::RoundClosed.into());
+    }
+    check_agent_is_player_current_session(game_session.clone())?;
+    check_round_is_open(&round_entry_hash)?;
+
+    if resource_amount > game_round.state.resources_left {
+        return Err(Error::NotEnoughResources(game_round.state.resources_left).into());
+    }
+
+    let me = agent_info()?.agent_initial_pubkey;
+    let moves = get_moves_for_round(&game_round_element)?;
+    if moves.iter().any(|m| m.owner == me) {
+        return Err(Error::DuplicateMove.into());
+    }
+    let team = game_session.team_of(&me);
+    if let Some(Team {
+        name,
+        round_quota: Some(quota),
+        ..
+    }) = team
+    {
+        // team members share the quota, so whatever they took this round is gone already
+        let taken_by_team: ResourceAmount = moves
+            .iter()
+            .filter(|m| m.team.as_ref() == Some(name))
+            .map(|m| m.resources)
+            .sum();
+        if taken_by_team + resource_amount > *quota {
+            return Err(Error::TeamQuotaExceeded {
+                team: name.clone(),
+                quota: *quota,
+            }
+            .into());
+        }
+    }
 
     // todo: add guard clauses for empty input
     debug!(
@@ -55,9 +103,10 @@ pub fn new_move(
         round_entry_hash, resource_amount
     );
     let game_move = GameMove {
-        owner: agent_info()?.agent_initial_pubkey,
+        owner: me,
         resources: resource_amount,
         round: round_entry_hash.clone(),
+        team: team.map(|t| t.name.clone()),
     };
     create_entry(&game_move);
     let entry_hash_game_move = hash_entry(&game_move)?;
@@ -79,70 +128,93 @@ pub fn new_move(
     Ok(header_hash_link)
 }
 
+/// Checks that the moves of every team sum up to no more than its round quota.
+/// Every move is checked against the quota when it's made, but moves made at
+/// the same time can only be checked together, once the round gets closed
+pub fn check_team_quotas(game_session: &GameSession, moves: &[GameMove]) -> Result<(), Error> {
+    for team in game_session.teams.iter() {
+        if let Some(quota) = team.round_quota {
+            let taken_by_team: ResourceAmount = moves
+                .iter()
+                .filter(|m| team.members.contains(&m.owner))
+                .map(|m| m.resources)
+                .sum();
+            if taken_by_team > quota {
+                return Err(Error::TeamQuotaExceeded {
+                    team: team.name.clone(),
+                    quota,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Round is closed as soon as its entry got updated with the next round
+fn check_round_is_open(round_entry_hash: &EntryHash) -> ExternResult<()> {
+    match get_details(round_entry_hash.clone(), GetOptions::latest())? {
+        Some(Details::Entry(details)) if details.updates.is_empty() => Ok(()),
+        Some(Details::Entry(_)) => Err(Error::RoundClosed.into()),
+        _ => Err(Error::RoundNotFound.into()),
+    }
+}
+
 pub fn get_moves_for_round(last_round_element: &Element) -> ExternResult<Vec<GameMove>> {
+    Ok(get_move_entries_for_round(last_round_element)?
+        .into_iter()
+        .map(|(_, game_move)| game_move)
+        .collect())
+}
+
+/// Retrieves all moves linked to the round together with their entry hashes
+pub fn get_move_entries_for_round(
+    last_round_element: &Element,
+) -> ExternResult<Vec<(EntryHash, GameMove)>> {
+    let mut moves: Vec<(EntryHash, GameMove)> = vec![];
+    for (move_hash, game_move_element) in get_move_elements_for_round(last_round_element)? {
+        let game_move: GameMove = entry_from_element_create_or_update(&game_move_element)?;
+        moves.push((move_hash, game_move));
+    }
+    Ok(moves)
+}
+
+/// Retrieves elements of all moves linked to the round together with their entry hashes.
+/// Elements are useful when we need more than the move itself, e.g. its timestamp
+pub fn get_move_elements_for_round(
+    last_round_element: &Element,
+) -> ExternResult<Vec<(EntryHash, Element)>> {
     info!("fetching links to game moves");
     let links = get_links(
         entry_hash_from_element(last_round_element)?.to_owned(),
         Some(LinkTag::new(String::from(GAME_MOVE_LINK_TAG))),
     )?;
-    let mut moves: Vec<GameMove> = vec![];
+    let mut elements: Vec<(EntryHash, Element)> = vec![];
     for link in links.into_inner() {
         debug!("fetching game move element, trying locally first");
         let game_move_element = match get(link.target.clone(), GetOptions::latest())? {
             Some(element) => element,
-            None => return Err(WasmError::Guest("Game move not found".into())),
+            None => return Err(Error::MoveNotFound.into()),
         };
-        let game_move: GameMove = entry_from_element_create_or_update(&game_move_element)?;
-        moves.push(game_move);
+        elements.push((link.target, game_move_element));
     }
-    Ok(moves)
+    Ok(elements)
 }
 
-/// Consumes list of moves passed to it to finalize them.
-/// If every player made at least one move, it returns list of moves which is guaranteed
-/// to have a single move for every player.
-/// If there are missing moves, it returns None, since we can't finalize the moves and
-/// have to wait for other players instead.
+/// Picks a single move of every player with the game rules, see
+/// tragedy_of_commons_rules::finalize_moves. Returns None until every player has moved
 pub fn finalize_moves(
-    moves: Vec<GameMove>,
+    moves: Vec<(EntryHash, GameMove)>,
     number_of_players: usize,
-) -> ExternResult<Option<Vec<GameMove>>> {
+) -> ExternResult<Option<Vec<(EntryHash, GameMove)>>> {
     info!("checking number of moves");
     debug!("moves list #{:?}", moves);
-    // Check that at least we have as many moves
-    // as there are players in the game
-    if moves.len() < number_of_players {
+    let unique_moves = tragedy_of_commons_rules::finalize_moves(moves, number_of_players, |m| {
+        m.owner.clone()
+    });
+    if unique_moves.is_none() {
         info!("Cannot close round: wait until all moves are made");
-        debug!("number of moves found: #{:?}", moves.len());
-        return Ok(None);
-    } else {
-        // Now that we know we have moves >= num of players, we need
-        // to make sure that every player made at least one move, so
-        // we're not closing the round without someone's move
-        let mut moves_per_player: BTreeMap<AgentPubKey, Vec<GameMove>> = BTreeMap::new();
-        for m in moves {
-            match moves_per_player.get_mut(&m.owner) {
-                Some(mut moves) => moves.push(m),
-                // TODO(e-nastasia): cloning owner value seems like a waste, but I think
-                // that alternative would be to use lifetimes. Not sure it's worth the
-                // readability penalty that we'll incur.
-                None => {
-                    moves_per_player.insert(m.owner.clone(), vec![m]);
-                }
-            }
-        }
-        if moves_per_player.keys().len() < number_of_players {
-            info!("Cannot close the round: only {} players made their moves, waiting for total {} players", moves_per_player.keys().len(), number_of_players);
-            return Ok(None);
-        }
-        let mut new_moves = vec![];
-        for (owner, move_vec) in moves_per_player {
-            // NOTE(e-nastasia): if we add a timestamp to the game move, we'll be able to
-            // filter moves here, but for now we'll do with just taking some move
-            new_moves.push(move_vec[0].clone());
-        }
-        Ok(Some(new_moves))
     }
+    Ok(unique_moves)
 }
 
 /*
@@ -188,6 +260,36 @@ pub fn validate_create_entry_game_move(data: ValidateData) -> ExternResult<Valid
     if !game_session.players.contains(&game_move.owner) {
         return Ok(ValidateCallbackResult::Invalid(String::from("Can't make a GameMove for this GameSession because move owner isn't in the list of GameSession players")));
     }
+    // rounds only check the owners of the moves they count, so owner has to be the author
+    if &game_move.owner != data.element.header().author() {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "GameMove can only be made by its owner",
+        )));
+    }
+
+    // nobody can take more than is left, moves made together get rationed when the round closes
+    if game_move.resources > game_round.state.resources_left {
+        return Ok(ValidateCallbackResult::Invalid(format!(
+            "GameMove takes {} resources, but only {} are left",
+            game_move.resources, game_round.state.resources_left
+        )));
+    }
+
+    // players can only harvest for their own team, and never more than the whole team may
+    let team = game_session.team_of(&game_move.owner);
+    if game_move.team.as_ref() != team.map(|t| &t.name) {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "GameMove can only be made for the team of its owner",
+        )));
+    }
+    if let Some(quota) = team.and_then(|t| t.round_quota) {
+        if game_move.resources > quota {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "GameMove takes {} resources, but the round quota of the team is {}",
+                game_move.resources, quota
+            )));
+        }
+    }
 
     // TODO(e-nastasia): validate that timestamp is later than game_session timestamp
 
@@ -0,0 +1,249 @@
+use crate::error::Error;
+use crate::game_round::{get_game_history, GameHistory};
+use crate::game_session::{SessionState, PLAYER_SESSION_TAG};
+use crate::types::ResourceAmount;
+use hdk::prelude::*;
+use std::collections::BTreeMap;
+use tragedy_of_commons_rules::{gini_coefficient, sustainable_harvest, BotStrategy};
+
+/// Final results of the game session, calculated from its full history
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct GameResults {
+    pub game_session_hash: EntryHash,
+    pub commons_survived: bool,
+    pub players: Vec<PlayerResult>,
+    /// Seats played by bots, ranked together with the players
+    pub bots: Vec<BotResult>,
+    /// Number of rounds the game was set to last, which players might not have known
+    pub game_length: u32,
+    /// Resources left in the commons at the start and after every round
+    pub resource_trajectory: Vec<ResourceAmount>,
+    pub total_harvest: ResourceAmount,
+    /// 0 means everyone harvested the same, values close to 1 mean one player took it all
+    pub gini_coefficient: f32,
+    /// Harvest per round that the commons could sustain forever from its start amount
+    pub sustainable_harvest_per_round: f32,
+    pub average_harvest_per_round: f32,
+    /// Average harvest relative to the sustainable one: above 1 means the group
+    /// over-harvested. None if the commons doesn't regenerate at all
+    pub sustainability_ratio: Option<f32>,
+}
+
+/// Results of a single player
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlayerResult {
+    pub player: AgentPubKey,
+    pub nickname: String,
+    pub harvest: ResourceAmount,
+    /// 1 for the biggest harvest, players with equal harvest share the rank
+    pub rank: usize,
+    /// Part of the total harvest taken by this player, from 0 to 1
+    pub share: f32,
+    pub harvest_per_round: Vec<ResourceAmount>,
+}
+
+/// Results of a single bot seat
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BotResult {
+    pub name: String,
+    pub strategy: BotStrategy,
+    pub harvest: ResourceAmount,
+    pub rank: usize,
+    pub share: f32,
+    pub harvest_per_round: Vec<ResourceAmount>,
+}
+
+/// Statistics of a single agent over all the games they have played
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct PlayerGameStats {
+    pub agent: AgentPubKey,
+    pub games_played: usize,
+    /// Games that ended with the commons still alive
+    pub games_won: usize,
+    /// Games that ended with the commons depleted
+    pub games_lost: usize,
+    /// Games that ended one way or another, i.e. games_won + games_lost
+    pub games_finished: usize,
+    pub rounds_played: usize,
+    pub total_harvest: ResourceAmount,
+    pub average_harvest_per_round: f32,
+    /// Number of rounds where the agent took more than their fair share
+    /// of the sustainable harvest
+    pub over_harvest_rounds: usize,
+    /// Part of the rounds played where the agent over-harvested, from 0 to 1
+    pub over_harvest_rate: f32,
+}
+
+/// Whether the session has ended with anything left in the commons. Its last round is
+/// the one calculated from the final moves, see get_game_history
+fn commons_survived(history: &GameHistory) -> bool {
+    history.status != SessionState::InProgress
+        && history
+            .rounds
+            .last()
+            .map_or(false, |round| round.state.commons_survived())
+}
+
+/// Calculates results of the finished session. Takes hash of any version of the session
+pub fn get_game_results(game_session_hash: EntryHash) -> ExternResult<GameResults> {
+    let history = get_game_history(game_session_hash)?;
+    if history.status == SessionState::InProgress {
+        return Err(Error::GameInProgress.into());
+    }
+    Ok(calculate_game_results(history))
+}
+
+fn calculate_game_results(history: GameHistory) -> GameResults {
+    let rounds_played = history.rounds.len();
+    let mut players: BTreeMap<AgentPubKey, PlayerResult> = BTreeMap::new();
+    for (round_index, round) in history.rounds.iter().enumerate() {
+        for game_move in &round.moves {
+            let result = players
+                .entry(game_move.owner.clone())
+                .or_insert_with(|| PlayerResult {
+                    player: game_move.owner.clone(),
+                    nickname: game_move.nickname.clone(),
+                    harvest: 0,
+                    rank: 0,
+                    share: 0.0,
+                    harvest_per_round: vec![0; rounds_played],
+                });
+            result.harvest += game_move.resources;
+            result.harvest_per_round[round_index] += game_move.resources;
+        }
+    }
+    let mut players: Vec<PlayerResult> = players.into_iter().map(|(_, p)| p).collect();
+    let mut bots: Vec<BotResult> = history
+        .game_params
+        .bots
+        .iter()
+        .map(|bot| {
+            let harvest_per_round: Vec<ResourceAmount> = history
+                .rounds
+                .iter()
+                .map(|round| round.state.bot_stats.get(&bot.name).cloned().unwrap_or(0))
+                .collect();
+            BotResult {
+                name: bot.name.clone(),
+                strategy: bot.strategy.clone(),
+                harvest: harvest_per_round.iter().sum(),
+                rank: 0,
+                share: 0.0,
+                harvest_per_round,
+            }
+        })
+        .collect();
+    let harvests: Vec<ResourceAmount> = players
+        .iter()
+        .map(|p| p.harvest)
+        .chain(bots.iter().map(|b| b.harvest))
+        .collect();
+    let total_harvest: ResourceAmount = harvests.iter().sum();
+    let rank_and_share = |harvest: ResourceAmount| {
+        let rank = 1 + harvests.iter().filter(|h| **h > harvest).count();
+        let share = if total_harvest > 0 {
+            harvest as f32 / total_harvest as f32
+        } else {
+            0.0
+        };
+        (rank, share)
+    };
+    for player in players.iter_mut() {
+        let (rank, share) = rank_and_share(player.harvest);
+        player.rank = rank;
+        player.share = share;
+    }
+    for bot in bots.iter_mut() {
+        let (rank, share) = rank_and_share(bot.harvest);
+        bot.rank = rank;
+        bot.share = share;
+    }
+    players.sort_by_key(|p| p.rank);
+    bots.sort_by_key(|b| b.rank);
+
+    let mut resource_trajectory = vec![history.game_params.start_amount];
+    resource_trajectory.extend(history.rounds.iter().map(|r| r.state.resources_left));
+
+    let sustainable_harvest_per_round = sustainable_harvest(
+        history.game_params.start_amount,
+        history.game_params.regeneration_factor,
+    );
+    let average_harvest_per_round = if rounds_played > 0 {
+        total_harvest as f32 / rounds_played as f32
+    } else {
+        0.0
+    };
+    let sustainability_ratio = if sustainable_harvest_per_round > 0.0 {
+        Some(average_harvest_per_round / sustainable_harvest_per_round)
+    } else {
+        None
+    };
+
+    GameResults {
+        game_session_hash: history.game_session_hash,
+        commons_survived: commons_survived(&history),
+        players,
+        bots,
+        game_length: history.game_params.game_length(history.game_session_hash.get_raw_39()),
+        resource_trajectory,
+        total_harvest,
+        gini_coefficient: gini_coefficient(&harvests),
+        sustainable_harvest_per_round,
+        average_harvest_per_round,
+        sustainability_ratio,
+    }
+}
+
+/// Aggregates statistics of the agent over every session they were linked to when it started
+pub fn get_player_stats(agent: AgentPubKey) -> ExternResult<PlayerGameStats> {
+    let links = get_links(
+        agent.clone().into(),
+        Some(LinkTag::new(PLAYER_SESSION_TAG)),
+    )?;
+    let mut stats = PlayerGameStats {
+        agent: agent.clone(),
+        games_played: 0,
+        games_won: 0,
+        games_lost: 0,
+        games_finished: 0,
+        rounds_played: 0,
+        total_harvest: 0,
+        average_harvest_per_round: 0.0,
+        over_harvest_rounds: 0,
+        over_harvest_rate: 0.0,
+    };
+    for link in links.into_inner() {
+        let history = get_game_history(link.target)?;
+        stats.games_played += 1;
+        if commons_survived(&history) {
+            stats.games_won += 1;
+        } else if history.status != SessionState::InProgress {
+            stats.games_lost += 1;
+        }
+        // every player moves in every round, so the first round tells how many players there are,
+        // and bots take their share of the commons just like players do
+        let players_total = match history.rounds.first() {
+            Some(round) if !round.moves.is_empty() => round.moves.len(),
+            _ => continue,
+        } + history.game_params.bots.len();
+        let fair_share = sustainable_harvest(
+            history.game_params.start_amount,
+            history.game_params.regeneration_factor,
+        ) / players_total as f32;
+        for round in history.rounds.iter() {
+            if let Some(my_move) = round.moves.iter().find(|m| m.owner == agent) {
+                stats.rounds_played += 1;
+                stats.total_harvest += my_move.resources;
+                if my_move.resources as f32 > fair_share {
+                    stats.over_harvest_rounds += 1;
+                }
+            }
+        }
+    }
+    stats.games_finished = stats.games_won + stats.games_lost;
+    if stats.rounds_played > 0 {
+        stats.average_harvest_per_round = stats.total_harvest as f32 / stats.rounds_played as f32;
+        stats.over_harvest_rate = stats.over_harvest_rounds as f32 / stats.rounds_played as f32;
+    }
+    Ok(stats)
+}
@@ -1,24 +1,30 @@
+use crate::error::Error;
 use crate::game_code::calculate_game_code_anchor_entry_hash;
-use crate::game_move::{finalize_moves, get_moves_for_round, GameMove};
+use crate::game_move::{
+    check_team_quotas, finalize_moves, get_move_entries_for_round, get_moves_for_round, GameMove,
+};
 use crate::game_session::{
-    GameParams, GameScores, GameSession, SessionState, GameSignal, SignalPayload,
+    get_original_and_latest_session, GameOutcome, GameParams, GameScores, GameSession, GameSignal,
+    SessionState, SignalPayload, SESSION_TO_ROUND_TAG,
 };
-use crate::types::{player_stats_from_moves, PlayerStats, ResourceAmount};
+use crate::player_profile::get_nicknames_for_anchor;
+use crate::types::{PlayerStats, ResourceAmount, TeamStats};
 use crate::utils::{
     check_agent_is_player_current_session, convert_keys_from_b64,
-    entry_from_element_create_or_update, entry_hash_from_element, must_get_entry_struct,
-    must_get_header_and_entry,
+    entry_from_element_create_or_update, entry_hash_from_element, get_canonical_update,
+    must_get_entry_struct, must_get_header_and_entry,
 };
 use hdk::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
 use std::vec;
+use tragedy_of_commons_rules::BotStats;
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct RoundState {
-    pub resources_left: ResourceAmount,
-    pub resources_taken: ResourceAmount,
-    pub resources_grown: ResourceAmount,
-    pub player_stats: PlayerStats,
-}
+/// How long the round can stay open before any player can close it,
+/// in case its designated closer is gone
+pub const CLOSER_TIMEOUT_MICROS: i64 = 5 * 60 * 1_000_000;
+
+/// State of the commons after a round, as calculated by the game rules
+pub type RoundState = tragedy_of_commons_rules::RoundState<AgentPubKey>;
 
 #[hdk_entry(id = "game_round", visibility = "public")]
 #[derive(Clone, PartialEq, Eq)]
@@ -26,34 +32,96 @@ pub struct GameRound {
     pub round_num: u32,
     pub session: EntryHash,
     pub state: RoundState,
+    /// Moves of the previous round that this round's state was calculated from
+    pub previous_round_moves: Vec<EntryHash>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
 pub struct GameRoundInfo {
     pub round_num: u32,
-    pub resources_left: Option<ResourceAmount>,
-    pub resources_taken_round: Option<ResourceAmount>,
-    pub resources_grown_round: Option<ResourceAmount>,
-    pub current_round_entry_hash: Option<EntryHash>,
-    pub prev_round_entry_hash: Option<EntryHash>,
-    pub game_session_hash: Option<EntryHash>,
-    pub next_action: String,
-    pub moves: Vec<(ResourceAmount, String, AgentPubKey)>,
+    pub prev_round_entry_hash: EntryHash,
+    pub game_session_hash: EntryHash,
+    pub next_action: RoundOutcome,
 }
 
-impl RoundState {
-    /// Creates a new RoundState instance with the provided input
-    pub fn new(
-        resources_left: ResourceAmount,
-        resources_taken: ResourceAmount,
-        resources_grown: ResourceAmount,
-        player_stats: PlayerStats,
-    ) -> RoundState {
-        RoundState {
-            resources_left,
-            resources_taken,
-            resources_grown,
-            player_stats,
+/// Outcome of the attempt to close the round, which tells the UI what to do next.
+/// It is serialized with the variant name in the "type" field, e.g.
+/// { type: "WAITING", moves_made: 1, players_total: 2 }
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RoundOutcome {
+    /// Not every player made their move yet
+    Waiting {
+        moves_made: usize,
+        players_total: usize,
+    },
+    /// Every player made their move, but it's up to another player to close the round
+    WaitingForCloser { closer: AgentPubKey },
+    /// Round is closed and the next one has started
+    NextRound {
+        round_entry_hash: EntryHash,
+        results: RoundResults,
+    },
+    /// Round is closed and it was the last one in the game
+    GameOver {
+        outcome: GameOutcome,
+        scores: PlayerStats,
+        results: RoundResults,
+    },
+}
+
+/// Results of the closed round
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoundResults {
+    pub resources_left: ResourceAmount,
+    pub resources_taken: ResourceAmount,
+    pub resources_grown: ResourceAmount,
+    pub moves: Vec<MoveInfo>,
+}
+
+/// Move made in the round, with the nickname of the player who made it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MoveInfo {
+    pub owner: AgentPubKey,
+    pub nickname: String,
+    pub resources: ResourceAmount,
+}
+
+/// Moves made so far in the round, as seen by the calling player
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct RoundStatus {
+    pub round_num: u32,
+    pub has_moved: bool,
+    pub my_resources: Option<ResourceAmount>,
+    pub moves_made: usize,
+    pub players_total: usize,
+    pub pending_players: Vec<AgentPubKey>,
+}
+
+/// Everything that happened in the game session, round by round
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct GameHistory {
+    pub game_session_hash: EntryHash,
+    pub status: SessionState,
+    pub game_params: GameParams,
+    pub rounds: Vec<RoundHistory>,
+}
+
+/// Moves made in a played round and the state they led to
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoundHistory {
+    pub round_num: u32,
+    pub state: RoundState,
+    pub moves: Vec<MoveInfo>,
+}
+
+impl RoundResults {
+    fn new(round_state: &RoundState, moves: Vec<MoveInfo>) -> RoundResults {
+        RoundResults {
+            resources_left: round_state.resources_left,
+            resources_taken: round_state.resources_taken,
+            resources_grown: round_state.resources_grown,
+            moves,
         }
     }
 }
@@ -66,18 +134,21 @@ impl GameRound {
         resources_left: ResourceAmount,
         resources_taken: ResourceAmount,
         resources_grown: ResourceAmount,
-        // previous_round_moves: Vec<EntryHash>,
+        previous_round_moves: Vec<EntryHash>,
     ) -> GameRound {
         let state = RoundState::new(
             resources_left,
             resources_taken,
             resources_grown,
             PlayerStats::new(),
+            TeamStats::new(),
+            BotStats::new(),
         );
         GameRound {
             round_num,
             session,
             state,
+            previous_round_moves,
         }
     }
 }
@@ -89,24 +160,13 @@ pub fn calculate_round_state(
     params: &GameParams,
     player_moves: Vec<GameMove>,
 ) -> RoundState {
-    // resources
-    let consumed_resources_in_round: ResourceAmount =
-        player_moves.iter().map(|x| x.resources).sum();
-    let resources_left = last_round.state.resources_left - consumed_resources_in_round;
-    let total_leftover_resource =
-            (resources_left as f32 * params.regeneration_factor) as i32;
-    let grown_resources_in_round = total_leftover_resource - resources_left;
-
-    // player stats dd
-    let player_stats = player_stats_from_moves(player_moves);
-    info!("total_leftover_resource : {:?}", total_leftover_resource);
-
-    RoundState {
-        resources_left: total_leftover_resource,
-        resources_taken: consumed_resources_in_round,
-        resources_grown: grown_resources_in_round,
-        player_stats,
-    }
+    tragedy_of_commons_rules::calculate_round_state(
+        last_round.round_num,
+        &last_round.state,
+        last_round.session.get_raw_39(),
+        params,
+        player_moves.iter().map(GameMove::to_move).collect(),
+    )
 }
 
 fn get_latest_round(header_hash: HeaderHash) -> ExternResult<(GameRound, EntryHash)> {
@@ -114,7 +174,7 @@ fn get_latest_round(header_hash: HeaderHash) -> ExternResult<(GameRound, EntryHa
     debug!("headerhash previous round: {:?}", header_hash.clone());
     let round_element = match get(header_hash, GetOptions::latest())? {
         Some(element) => element,
-        None => return Err(WasmError::Guest("Round not found".into())),
+        None => return Err(Error::RoundNotFound.into()),
     };
     debug!("extracting game round from element");
     let last_round: GameRound = entry_from_element_create_or_update(&round_element)?;
@@ -157,124 +217,310 @@ fn get_latest_round(header_hash: HeaderHash) -> ExternResult<(GameRound, EntryHa
 // would actually be a game session entry) and attempt to close the current round by creating it's entry.
 // This would solely depend on the amount of moves retrieved being equal to the amount of players in the game
 
+/// Read-only check of the moves made in the round: only reads from the DHT,
+/// so it is safe to poll it as often as the UI likes
+pub fn round_status(round_hash: EntryHash) -> ExternResult<RoundStatus> {
+    let round_element = match get(round_hash, GetOptions::latest())? {
+        Some(element) => element,
+        None => return Err(Error::RoundNotFound.into()),
+    };
+    let round: GameRound = entry_from_element_create_or_update(&round_element)?;
+    let game_session = match get(round.session.clone(), GetOptions::latest())? {
+        Some(element) => entry_from_element_create_or_update::<GameSession>(&element)?,
+        None => return Err(Error::GameNotFound.into()),
+    };
+    let moves = get_moves_for_round(&round_element)?;
+    let me = agent_info()?.agent_initial_pubkey;
+    let my_resources = moves
+        .iter()
+        .find(|m| m.owner == me)
+        .map(|m| m.resources);
+    let pending_players: Vec<AgentPubKey> = game_session
+        .players
+        .iter()
+        .filter(|player| !moves.iter().any(|m| &m.owner == *player))
+        .cloned()
+        .collect();
+    let players_total = game_session.players.len();
+    Ok(RoundStatus {
+        round_num: round.round_num,
+        has_moved: my_resources.is_some(),
+        my_resources,
+        moves_made: players_total - pending_players.len(),
+        players_total,
+        pending_players,
+    })
+}
+
 pub fn try_to_close_round(last_round_hash: EntryHash) -> ExternResult<GameRoundInfo> {
     //previous round
     info!("fetching element with previous round from DHT");
     debug!("entry hash previous round: {:?}", last_round_hash.clone());
     let last_round_element = match get(last_round_hash.clone(), GetOptions::latest())? {
         Some(element) => element,
-        None => return Err(WasmError::Guest("Previous round not found".into())),
+        None => return Err(Error::RoundNotFound.into()),
     };
     let last_round: GameRound = entry_from_element_create_or_update(&last_round_element)?;
 
-    // TODO optimization: check if new round with next round_num already exists
-    // in that case it is not necessary create it again
-
     // game session
     // fetching element with game session from DHT, trying locally first
     let game_session_element = match get(last_round.session.clone(), GetOptions::latest())? {
         Some(element) => element,
-        None => return Err(WasmError::Guest("Game session not found".into())),
+        None => return Err(Error::GameNotFound.into()),
     };
     let game_session: GameSession = entry_from_element_create_or_update(&game_session_element)?;
 
     // game moves
-    let moves = get_moves_for_round(&last_round_element)?;
+    let moves = get_move_entries_for_round(&last_round_element)?;
+
+    let game_session_hash = entry_hash_from_element(&game_session_element)?.clone();
+    let players_total = game_session.players.len();
+    let moves_made = moves
+        .iter()
+        .map(|(_, m)| &m.owner)
+        .collect::<BTreeSet<&AgentPubKey>>()
+        .len();
 
     // try to get all moves necessary to close the round
-    match finalize_moves(moves, game_session.players.len())? {
-        // we get the moves, so we can close the round
-        Some(unique_moves) => {
-            // TODO: convert Vec<GameMove> into something for nice printing
-            let mut moves_info: Vec<(i32, String, AgentPubKey)> = vec![];
-            for game_move in &unique_moves {
-                moves_info.push((
-                    game_move.resources.clone(),
-                    "playername".into(),
-                    game_move.owner.clone(),
-                ));
-            }
-            info!("all players made their moves: calculating round state");
-            let round_state =
-                calculate_round_state(&last_round, &game_session.game_params, unique_moves);
-            if start_new_round(&game_session, &last_round, &round_state) {
-                let hash = create_new_round(
-                    &game_session,
-                    &last_round,
-                    last_round_element.header_address(),
-                    &round_state,
-                )?;
-                return Ok(GameRoundInfo {
-                    current_round_entry_hash: Some(hash),
-                    prev_round_entry_hash: Some(last_round_hash),
-                    game_session_hash: None,
-                    resources_left: Some(round_state.resources_left),
-                    resources_taken_round: Some(round_state.resources_taken),
-                    resources_grown_round: Some(round_state.resources_grown),
-                    round_num: last_round.round_num + 1,
-                    next_action: "START_NEXT_ROUND".into(),
-                    moves: moves_info,
-                });
-                //round_hash + next action
-            } else {
-                let hash = crate::game_session::end_game(
-                    &game_session,
-                    &game_session_element.header_address(),
-                    &last_round,
-                    last_round_element
-                        .header()
-                        .entry_hash()
-                        .expect("should have entry"),
-                    &round_state,
-                )?;
-                return Ok(GameRoundInfo {
-                    current_round_entry_hash: None,
-                    prev_round_entry_hash: Some(last_round_hash),
-                    game_session_hash: Some(hash),
-                    resources_left: Some(round_state.resources_left),
-                    resources_taken_round: Some(round_state.resources_taken),
-                    resources_grown_round: Some(round_state.resources_grown),
-                    round_num: last_round.round_num + 1,
-                    next_action: "SHOW_GAME_RESULTS".into(),
-                    moves: moves_info,
-                });
-                //game_session_hash + next action
-            }
-        }
+    let unique_moves = match finalize_moves(moves, players_total)? {
+        Some(unique_moves) => unique_moves,
         // There aren't enough moves yet, so we get nothing and wait
         None => {
-            // TODO: fix the value in current_round_entry_hash: Some(last_round_hash)
             return Ok(GameRoundInfo {
-                current_round_entry_hash: None,
-                prev_round_entry_hash: Some(last_round_hash),
-                game_session_hash: Some(
-                    game_session_element
-                        .header()
-                        .entry_hash()
-                        .expect("should have entry")
-                        .clone(),
-                ),
-                resources_left: None,
-                resources_taken_round: None,
-                resources_grown_round: None,
                 round_num: last_round.round_num,
-                next_action: "WAITING".into(),
-                moves: vec![],
-                // add anonymous moves list
+                prev_round_entry_hash: last_round_hash,
+                game_session_hash,
+                next_action: RoundOutcome::Waiting {
+                    moves_made,
+                    players_total,
+                },
             });
         }
     };
+
+    // we get the moves, so we can close the round
+    // since the moves are picked deterministically, every player calculates
+    // exactly the same state, and we can safely reuse the round the closer created
+    let (move_hashes, unique_moves): (Vec<EntryHash>, Vec<GameMove>) =
+        unique_moves.into_iter().unzip();
+    check_team_quotas(&game_session, &unique_moves)?;
+    // nicknames are fetched once for all players, so we don't
+    // make DHT queries for every move
+    let nicknames = get_nicknames_for_anchor(game_session.anchor.clone())?;
+    let moves_info = moves_info(&unique_moves, &nicknames);
+    info!("all players made their moves: calculating round state");
+    let round_state = calculate_round_state(&last_round, &game_session.game_params, unique_moves);
+    let results = RoundResults::new(&round_state, moves_info);
+    let closer = designated_closer(&game_session, last_round.round_num);
+    let waiting_for_closer = GameRoundInfo {
+        round_num: last_round.round_num,
+        prev_round_entry_hash: last_round_hash.clone(),
+        game_session_hash: game_session_hash.clone(),
+        next_action: RoundOutcome::WaitingForCloser {
+            closer: closer.clone(),
+        },
+    };
+    let i_am_closer = can_close_round(
+        &game_session,
+        last_round.round_num,
+        &agent_info()?.agent_initial_pubkey,
+        &last_round_element.header().timestamp(),
+        &sys_time()?,
+    );
+    if start_new_round(&game_session, &last_round, &round_state) {
+        let hash = match get_canonical_update(last_round_hash.clone())? {
+            Some((_, existing_round_hash)) => {
+                debug!("next round already exists: {:?}", existing_round_hash);
+                existing_round_hash
+            }
+            None if !i_am_closer => return Ok(waiting_for_closer),
+            None => create_new_round(
+                &game_session,
+                &last_round,
+                last_round_element.header_address(),
+                &round_state,
+                move_hashes,
+            )?,
+        };
+        Ok(GameRoundInfo {
+            round_num: last_round.round_num + 1,
+            prev_round_entry_hash: last_round_hash,
+            game_session_hash,
+            next_action: RoundOutcome::NextRound {
+                round_entry_hash: hash,
+                results,
+            },
+        })
+    } else {
+        let hash = match get_canonical_update(game_session_hash.clone())? {
+            Some((_, ended_session_hash)) => {
+                debug!("game session is already over: {:?}", ended_session_hash);
+                ended_session_hash
+            }
+            None if !i_am_closer => return Ok(waiting_for_closer),
+            None => crate::game_session::end_game(
+                &game_session,
+                &game_session_element.header_address(),
+                &last_round,
+                &last_round_hash,
+                last_round_element.header_address(),
+                &round_state,
+                move_hashes,
+            )?,
+        };
+        Ok(GameRoundInfo {
+            round_num: last_round.round_num + 1,
+            prev_round_entry_hash: last_round_hash,
+            game_session_hash: hash,
+            next_action: RoundOutcome::GameOver {
+                outcome: GameOutcome::from_round_state(&round_state),
+                scores: round_state.player_stats.clone(),
+                results,
+            },
+        })
+    }
 }
 
-fn start_new_round(
+fn moves_info(moves: &[GameMove], nicknames: &BTreeMap<AgentPubKey, String>) -> Vec<MoveInfo> {
+    moves
+        .iter()
+        .map(|game_move| MoveInfo {
+            owner: game_move.owner.clone(),
+            nickname: nicknames
+                .get(&game_move.owner)
+                .cloned()
+                .unwrap_or_else(|| String::from("unknown player")),
+            resources: game_move.resources,
+        })
+        .collect()
+}
+
+/// Collects all the rounds of the session with their state and the moves players made.
+/// Takes hash of any version of the session, e.g. the one from the GameOver signal
+pub fn get_game_history(game_session_hash: EntryHash) -> ExternResult<GameHistory> {
+    let (original_session_hash, game_session) =
+        get_original_and_latest_session(game_session_hash)?;
+    let nicknames = get_nicknames_for_anchor(game_session.anchor.clone())?;
+
+    // every round after round zero stores the moves of the previous one it was calculated from
+    let round_chain = get_round_chain(original_session_hash.clone())?;
+    let mut rounds = vec![];
+    for (_, element) in round_chain.iter().skip(1) {
+        let round: GameRound = entry_from_element_create_or_update(element)?;
+        let mut moves = vec![];
+        for move_hash in &round.previous_round_moves {
+            match get(move_hash.clone(), GetOptions::latest())? {
+                Some(move_element) => {
+                    moves.push(entry_from_element_create_or_update::<GameMove>(&move_element)?)
+                }
+                None => return Err(Error::MoveNotFound.into()),
+            }
+        }
+        rounds.push(RoundHistory {
+            round_num: round.round_num,
+            state: round.state,
+            moves: moves_info(&moves, &nicknames),
+        });
+    }
+
+    // the last round doesn't get a successor when the game ends,
+    // so its state is calculated from its moves the same way end_game did
+    if game_session.status != SessionState::InProgress {
+        if let Some((_, last_round_element)) = round_chain.last() {
+            let last_round: GameRound = entry_from_element_create_or_update(last_round_element)?;
+            let moves = get_move_entries_for_round(last_round_element)?;
+            if let Some(unique_moves) = finalize_moves(moves, game_session.players.len())? {
+                let unique_moves: Vec<GameMove> =
+                    unique_moves.into_iter().map(|(_, m)| m).collect();
+                let moves_info = moves_info(&unique_moves, &nicknames);
+                rounds.push(RoundHistory {
+                    round_num: last_round.round_num + 1,
+                    state: calculate_round_state(
+                        &last_round,
+                        &game_session.game_params,
+                        unique_moves,
+                    ),
+                    moves: moves_info,
+                });
+            }
+        }
+    }
+
+    Ok(GameHistory {
+        game_session_hash: original_session_hash,
+        status: game_session.status,
+        game_params: game_session.game_params,
+        rounds,
+    })
+}
+
+/// Retrieves entry hashes and elements of all the rounds of the session, starting with
+/// round zero and following the canonical update of every round to the next one
+pub fn get_round_chain(
+    original_session_hash: EntryHash,
+) -> ExternResult<Vec<(EntryHash, Element)>> {
+    let round_zero_link = get_links(
+        original_session_hash,
+        Some(LinkTag::new(SESSION_TO_ROUND_TAG)),
+    )?
+    .into_inner()
+    .into_iter()
+    .min_by_key(|link| link.timestamp.clone());
+    let mut round_hash = match round_zero_link {
+        Some(link) => link.target,
+        None => return Err(Error::RoundNotFound.into()),
+    };
+    let mut round_chain = vec![];
+    loop {
+        let element = match get(round_hash.clone(), GetOptions::latest())? {
+            Some(element) => element,
+            None => return Err(Error::RoundNotFound.into()),
+        };
+        round_chain.push((round_hash.clone(), element));
+        match get_canonical_update(round_hash)? {
+            Some((_, next_round_hash)) => round_hash = next_round_hash,
+            None => break,
+        }
+    }
+    Ok(round_chain)
+}
+
+/// Player who is responsible for closing the round with the given number.
+/// Players take turns closing rounds in the order they are listed in the session,
+/// so only one of them updates the round entry and the update chain doesn't fork
+pub fn designated_closer(game_session: &GameSession, round_num: u32) -> AgentPubKey {
+    let index = round_num as usize % game_session.players.len();
+    game_session.players[index].clone()
+}
+
+/// Checks whether the agent can close the round that was opened at opened_at.
+/// Normally only the designated closer can, but in case the closer disappeared,
+/// any player can close the round once it was open for CLOSER_TIMEOUT_MICROS
+pub fn can_close_round(
+    game_session: &GameSession,
+    round_num: u32,
+    agent: &AgentPubKey,
+    opened_at: &Timestamp,
+    closed_at: &Timestamp,
+) -> bool {
+    if &designated_closer(game_session, round_num) == agent {
+        return true;
+    }
+    let open_micros = closed_at.as_micros() - opened_at.as_micros();
+    game_session.players.contains(agent) && open_micros >= CLOSER_TIMEOUT_MICROS
+}
+
+pub fn start_new_round(
     game_session: &GameSession,
     prev_round: &GameRound,
     round_state: &RoundState,
 ) -> bool {
-    // rounds left to play?
-    prev_round.round_num + 1 < game_session.game_params.num_rounds
-    // resources not depleted?
-        && round_state.resources_left > 0
+    tragedy_of_commons_rules::start_new_round(
+        &game_session.game_params,
+        prev_round.session.get_raw_39(),
+        prev_round.round_num,
+        round_state,
+    )
 }
 
 fn create_new_round(
@@ -282,6 +528,7 @@ fn create_new_round(
     last_round: &GameRound,
     last_round_header_hash: &HeaderHash,
     round_state: &RoundState,
+    previous_round_moves: Vec<EntryHash>,
 ) -> ExternResult<EntryHash> {
     info!(
         "start new round: updating game round entry. Last_round_num {:?}",
@@ -294,6 +541,7 @@ fn create_new_round(
         round_state.resources_left,
         round_state.resources_taken,
         round_state.resources_grown,
+        previous_round_moves,
     );
     debug!("new round: {:?}", next_round);
     let round_header_hash_update = update_entry(last_round_header_hash.clone(), &next_round)?;
@@ -342,23 +590,25 @@ pub fn current_round_for_game_code(game_code: String) -> ExternResult<Option<Ent
     let links_vec = links.into_inner();
     debug!("links: {:#?}", &links_vec);
 
-    if links_vec.len() > 0 {
-        if links_vec.len() > 1 {
-            // TODO find alternative for clone to get len
-            return Err(WasmError::Guest(String::from(
-                "More than one link from anchor to game session. Should not happen.",
-            )));
-        }
-        // should be only one link
-        let link = &links_vec[0];
-
-        debug!("link: {:#?}", link);
+    // there can be several sessions for the same game code when players
+    // asked for a rematch, and the latest one is the current one. Tournament groups
+    // play their sessions at the same time, so we only look at the caller's ones
+    let me = agent_info()?.agent_initial_pubkey;
+    let mut sessions = vec![];
+    for link in links_vec.iter() {
         let element: Element = get(link.target.clone(), GetOptions::latest())?
-            .ok_or(WasmError::Guest(String::from("Entry not found")))?;
+            .ok_or(Error::GameNotFound)?;
         let game_session: GameSession = element
             .entry()
             .to_app_option()?
             .expect("game session has to exist");
+        if game_session.tournament.is_none() || game_session.players.contains(&me) {
+            sessions.push((link.timestamp.clone(), element, game_session));
+        }
+    }
+    if let Some((_, element, game_session)) = sessions.into_iter().max_by_key(|s| s.0.clone()) {
+
+        debug!("session element: {:#?}", element);
         let game_session_entry_hash: &EntryHash = entry_hash_from_element(&element)?;
         let result = check_agent_is_player_current_session(game_session);
         match result {
@@ -385,7 +635,7 @@ pub fn current_round_for_game_code(game_code: String) -> ExternResult<Option<Ent
 
             debug!("link session round: {:#?}", &link);
             let element: Element = get(link.target.clone(), GetOptions::latest())?
-                .ok_or(WasmError::Guest(String::from("Entry not found")))?;
+                .ok_or(Error::RoundNotFound)?;
 
             //let game_round_entry_hash:&EntryHash = entry_hash_from_element(&element)?;
             return Ok(Some(
@@ -417,6 +667,11 @@ pub fn validate_create_entry_game_round(data: ValidateData) -> ExternResult<Vali
             game_round.round_num,
         )));
     }
+    if !game_round.previous_round_moves.is_empty() {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "GameRound with number 0 can't have any moves of the previous round",
+        )));
+    }
     let game_session = must_get_entry_struct::<GameSession>(game_round.session)?;
     if game_session.status != SessionState::InProgress {
         return Ok(ValidateCallbackResult::Invalid(format!(
@@ -442,11 +697,12 @@ pub fn validate_update_entry_game_round(
     //     game_round, data
     // );
 
-    let game_session = must_get_entry_struct::<GameSession>(game_round.session)?;
-    if game_round.round_num > game_session.game_params.num_rounds {
+    let game_session = must_get_entry_struct::<GameSession>(game_round.session.clone())?;
+    let game_length = game_session.game_params.game_length(game_round.session.get_raw_39());
+    if game_round.round_num >= game_length {
         return Ok(ValidateCallbackResult::Invalid(format!(
             "Can't update GameRound number {} because GameSession only has {} rounds",
-            game_round.round_num, game_session.game_params.num_rounds
+            game_round.round_num, game_length
         )));
     }
 
@@ -459,6 +715,47 @@ pub fn validate_update_entry_game_round(
             if (prev_entry.round_num + 1) != game_round.round_num {
                 return Ok(ValidateCallbackResult::Invalid(format!("Can't update GameRound entry to have round num {}: previous GameRound has num {}", game_round.round_num, prev_entry.round_num)));
             }
+            if prev_entry.session != game_round.session {
+                return Ok(ValidateCallbackResult::Invalid(String::from(
+                    "Can't move GameRound to another GameSession",
+                )));
+            }
+            let prev_header = must_get_header(update_data.original_header_address.clone())?;
+            let closer = designated_closer(&game_session, prev_entry.round_num);
+            let can_close = can_close_round(
+                &game_session,
+                prev_entry.round_num,
+                &update_data.author,
+                &prev_header.header().timestamp(),
+                &update_data.timestamp,
+            );
+            if !can_close {
+                return Ok(ValidateCallbackResult::Invalid(format!(
+                    "GameRound {} can only be closed by {:?} until it times out",
+                    prev_entry.round_num, closer
+                )));
+            }
+            let unique_moves = match must_get_closing_moves(
+                &update_data.original_entry_address,
+                &game_round.previous_round_moves,
+                &game_session,
+            )? {
+                Ok(unique_moves) => unique_moves,
+                Err(reason) => return Ok(ValidateCallbackResult::Invalid(reason)),
+            };
+            if let Err(reason) = check_team_quotas(&game_session, &unique_moves) {
+                return Ok(ValidateCallbackResult::Invalid(format!(
+                    "Can't close GameRound: {}",
+                    reason
+                )));
+            }
+            let expected_state =
+                calculate_round_state(&prev_entry, &game_session.game_params, unique_moves);
+            if expected_state != game_round.state {
+                return Ok(ValidateCallbackResult::Invalid(String::from(
+                    "GameRound state doesn't match the moves of the previous round",
+                )));
+            }
         }
         _ => {
             // TODO(e-nastasia): perhaps add there the type of header received, for a more informative error message
@@ -470,3 +767,41 @@ pub fn validate_update_entry_game_round(
 
     Ok(ValidateCallbackResult::Valid)
 }
+
+/// Fetches the moves the round was closed with. Moves are only fetched by their hashes,
+/// so every validator checks exactly the same moves: one move of every player of the
+/// session, made for the round. Returns the reason if the moves are not like that
+pub fn must_get_closing_moves(
+    round_hash: &EntryHash,
+    move_hashes: &[EntryHash],
+    game_session: &GameSession,
+) -> ExternResult<Result<Vec<GameMove>, String>> {
+    let mut moves: Vec<GameMove> = vec![];
+    for move_hash in move_hashes {
+        let game_move = must_get_entry_struct::<GameMove>(move_hash.clone())?;
+        if &game_move.round != round_hash {
+            return Ok(Err(format!(
+                "GameMove {:?} doesn't belong to the closed GameRound",
+                move_hash
+            )));
+        }
+        if !game_session.players.contains(&game_move.owner) {
+            return Ok(Err(format!(
+                "GameMove {:?} wasn't made by a player of the GameSession",
+                move_hash
+            )));
+        }
+        if moves.iter().any(|m| m.owner == game_move.owner) {
+            return Ok(Err(String::from(
+                "GameRound can only be closed with one move of every player",
+            )));
+        }
+        moves.push(game_move);
+    }
+    if moves.len() != game_session.players.len() {
+        return Ok(Err(String::from(
+            "GameRound has to be closed with a move of every player",
+        )));
+    }
+    Ok(Ok(moves))
+}
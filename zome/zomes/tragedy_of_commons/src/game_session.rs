@@ -1,6 +1,14 @@
 use crate::error::Error;
-use crate::types::{new_player_stats, PlayerStats, ResourceAmount};
-use crate::utils::{entry_from_element_create_or_update, entry_hash_from_element};
+use crate::game_lobby::{get_lobby_for_anchor, get_ready_players, GameLobby, PlayerReadyPayload};
+use crate::leaderboard::post_game_scores;
+use crate::tournament::{
+    start_next_stage_if_ready, validate_tournament_session, StageGameEndedPayload, TournamentMatch,
+};
+use crate::types::{new_player_stats, PlayerStats, ResourceAmount, TeamStats};
+use crate::utils::{
+    entry_from_element_create_or_update, entry_hash_from_element, get_canonical_update,
+    must_get_entry_struct,
+};
 use crate::PlayerProfile;
 use crate::{
     game_code::calculate_game_code_anchor_entry_hash,
@@ -9,11 +17,14 @@ use crate::{
 };
 
 use hdk::prelude::*;
-use std::{time::SystemTime};
+use std::{collections::BTreeSet, time::SystemTime};
+pub use tragedy_of_commons_rules::{GameOutcome, GameParams, RoundLength};
 
 pub const OWNER_SESSION_TAG: &str = "MY_GAMES";
 pub const GAME_CODE_TO_SESSION_TAG: &str = "GAME_SESSION";
 pub const SESSION_TO_ROUND_TAG: &str = "GAME_ROUND";
+pub const REMATCH_TAG: &str = "REMATCH";
+pub const PLAYER_SESSION_TAG: &str = "PLAYER_SESSION";
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum SessionState {
@@ -22,11 +33,64 @@ pub enum SessionState {
     Finished { last_round: EntryHash },
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, Copy)]
-pub struct GameParams {
-    pub regeneration_factor: f32,
-    pub start_amount: ResourceAmount,
-    pub num_rounds: u32,
+/// Players who harvest from the commons together: their moves count against
+/// a shared allocation and their scores are summed up
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Team {
+    pub name: String,
+    pub members: Vec<AgentPubKey>,
+    /// Resources the whole team can take in a single round, no limit if None
+    pub round_quota: Option<ResourceAmount>,
+}
+
+/// Checks that teams, if there are any, split the players so that every
+/// player is a member of exactly one team
+pub fn check_teams(players: &[AgentPubKey], teams: &[Team]) -> Result<(), Error> {
+    if teams.is_empty() {
+        return Ok(());
+    }
+    let mut names = BTreeSet::new();
+    for team in teams {
+        if team.name.trim().is_empty() {
+            return Err(Error::InvalidTeams(String::from("team name can't be empty")));
+        }
+        if !names.insert(team.name.clone()) {
+            return Err(Error::InvalidTeams(format!("team {} appears twice", team.name)));
+        }
+        if matches!(team.round_quota, Some(quota) if quota <= 0) {
+            return Err(Error::InvalidTeams(format!(
+                "round quota of team {} has to be positive",
+                team.name
+            )));
+        }
+        if let Some(member) = team.members.iter().find(|m| !players.contains(m)) {
+            return Err(Error::InvalidTeams(format!(
+                "{} of team {} isn't a player",
+                member, team.name
+            )));
+        }
+    }
+    for player in players {
+        let team_count = teams.iter().filter(|t| t.members.contains(player)).count();
+        if team_count != 1 {
+            return Err(Error::InvalidTeams(format!(
+                "player {} is a member of {} teams instead of one",
+                player, team_count
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Sums up scores of the players into the scores of their teams
+pub fn team_scores(scores: &PlayerStats, teams: &[Team]) -> TeamStats {
+    teams
+        .iter()
+        .map(|team| {
+            let total = team.members.iter().filter_map(|m| scores.get(m)).sum();
+            (team.name.clone(), total)
+        })
+        .collect()
 }
 
 #[hdk_entry(id = "game_session", visibility = "public")]
@@ -38,12 +102,41 @@ pub struct GameSession {
     pub players: Vec<AgentPubKey>, // who is playing
     pub scores: PlayerStats,       // end scores
     pub anchor: EntryHash,
+    pub lobby: EntryHash,          // lobby this session was started from
+    #[serde(default)]
+    pub tournament: Option<TournamentMatch>, // tournament stage this session is played in
+    #[serde(default)]
+    pub teams: Vec<Team>,          // empty if everyone plays for themselves
+    #[serde(default)]
+    pub team_scores: TeamStats,    // end scores of the teams
+    #[serde(default)]
+    pub previous_session: Option<EntryHash>, // session this one is a rematch of
+}
+
+impl GameSession {
+    /// Team the agent plays for, if the session is played in teams
+    pub fn team_of(&self, agent: &AgentPubKey) -> Option<&Team> {
+        self.teams.iter().find(|team| team.members.contains(agent))
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
 pub struct GameSessionInput {
     pub game_params: GameParams,
     pub players: Vec<AgentPubKey>,
+    #[serde(default)]
+    pub teams: Vec<Team>,
+}
+
+/// Struct to receive user input from the UI when host wants to start the game.
+/// Game only starts once all players are ready, unless host decides to force it.
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct StartGameInput {
+    pub game_code: String,
+    pub force: bool,
+    /// Players of the lobby split into teams, or empty to let everyone play for themselves
+    #[serde(default)]
+    pub teams: Vec<Team>,
 }
 
 #[derive(Debug, Serialize, Deserialize, SerializedBytes)]
@@ -57,35 +150,82 @@ pub struct SignalPayload {
 pub struct GameScores {
     pub game_session: GameSession,
     pub game_session_entry_hash: EntryHash,
-    //TODO add the actual results :-)
+    pub scores: PlayerStats, // harvest of every player summed over all rounds
+    pub total_harvest: ResourceAmount,
+    pub commons_survived: bool,
+    pub group: Option<String>, // group leaderboard these scores are posted to
+    #[serde(default)]
+    pub team_scores: TeamStats, // harvest of every team summed over all rounds
+    /// Headers of every version of the round entry, from round zero to the last round
+    #[serde(default)]
+    pub round_headers: Vec<HeaderHash>,
+    /// Moves of the last round, which the final state of the game was calculated from
+    #[serde(default)]
+    pub last_round_moves: Vec<EntryHash>,
 }
 
-/// Creates GameSession with the game_code and game_params
-// TODO(e-nastasia): actually add game_params to be used for creation
-pub fn start_game_session_with_code(game_code: String) -> ExternResult<EntryHash> {
+/// Creates GameSession with the game_code and game_params configured in its lobby.
+/// Only the host of the lobby can start the session, and only once.
+pub fn start_game_session_with_code(input: StartGameInput) -> ExternResult<EntryHash> {
+    let game_code = input.game_code;
     let anchor = calculate_game_code_anchor_entry_hash(game_code.clone())?;
     debug!("anchor: {:?}", anchor);
+    let lobby = match get_lobby_for_anchor(anchor.clone())? {
+        Some((_, lobby)) => lobby,
+        None => return Err(Error::LobbyNotFound.into()),
+    };
+    if lobby.host != agent_info()?.agent_initial_pubkey {
+        return Err(Error::NotTheHost.into());
+    }
+    let session_links = get_links(
+        anchor.clone(),
+        Some(LinkTag::new(GAME_CODE_TO_SESSION_TAG)),
+    )?;
+    if !session_links.into_inner().is_empty() {
+        return Err(Error::GameAlreadyStarted.into());
+    }
     let players = crate::player_profile::get_player_profiles_for_game_code(game_code)?;
     debug!("players: {:#?}", players);
-    let game_params = GameParams {
-        regeneration_factor: 1.1,
-        start_amount: 100,
-        num_rounds: 3,
-    };
+    if !input.force {
+        let ready_players = get_ready_players(anchor.clone())?;
+        if players.iter().any(|p| !ready_players.contains(&p.player_id)) {
+            return Err(Error::PlayersNotReady.into());
+        }
+    }
     let player_keys: Vec<AgentPubKey> = players.iter().map(|x| x.player_id.clone()).collect();
-    new_session(player_keys, game_params, anchor)
+    let lobby_entry_hash = hash_entry(&lobby)?;
+    new_session(
+        player_keys,
+        lobby.game_params,
+        anchor,
+        lobby_entry_hash,
+        None,
+        input.teams,
+        None,
+    )
 }
 
 /// Create a new GameSession with the confirmed players (who accepted their invites).
-/// NOTE: we're only creating session for those who accepted and only if there are at
-/// least two of them -- otherwise there won't be any turns.
+/// NOTE: we're only creating session if the number of players fits into the
+/// min_players..=max_players range of the game params -- otherwise there won't be any turns.
 pub fn new_session(
     players: Vec<AgentPubKey>,
     game_params: GameParams,
     anchor: EntryHash,
+    lobby: EntryHash,
+    tournament: Option<TournamentMatch>,
+    teams: Vec<Team>,
+    previous_session: Option<EntryHash>,
 ) -> ExternResult<EntryHash> {
     // TODO: get timestamp as systime
 
+    game_params
+        .check_player_count(players.len())
+        .map_err(Error::from)?;
+    game_params.check_round_length().map_err(Error::from)?;
+    game_params.check_bots().map_err(Error::from)?;
+    check_teams(&players, &teams)?;
+
     info!("creating new game session");
     // agent that starts new game
     let agent_info_owner = agent_info()?;
@@ -97,6 +237,11 @@ pub fn new_session(
         players: players.clone(),
         scores: PlayerStats::new(),
         anchor: anchor.clone(),
+        lobby,
+        tournament,
+        teams,
+        team_scores: TeamStats::new(),
+        previous_session,
     };
     let game_session_header_hash = create_entry(&game_session)?;
     let game_session_entry_hash = hash_entry(&game_session)?;
@@ -114,6 +259,17 @@ pub fn new_session(
         LinkTag::new(OWNER_SESSION_TAG),
     )?;
 
+    info!("linking players to game session");
+    // create links from every player's address to the game session entry,
+    // so we can find all the games an agent has played
+    for player in players.iter() {
+        create_link(
+            player.clone().into(),
+            game_session_entry_hash.clone(),
+            LinkTag::new(PLAYER_SESSION_TAG),
+        )?;
+    }
+
     info!("linking game code anchor to game session");
     // create link from session owner's address to the game session entry
     create_link(
@@ -133,8 +289,7 @@ pub fn new_session(
         game_session.game_params.start_amount,
         0,
         0,
-            // new_player_stats(&players),
-            // no_moves,
+        no_moves,
     );
     let header_hash_round_zero = create_entry(&round_zero)?;
     let entry_hash_round_zero = hash_entry(&round_zero)?;
@@ -143,7 +298,7 @@ pub fn new_session(
         game_session_entry_hash.clone(),
         entry_hash_round_zero.clone(),
         LinkTag::new(SESSION_TO_ROUND_TAG),
-    );
+    )?;
 
     // use remote signals from RSM to send a real-time notif to invited players
     //  ! using remote signal to ping other holochain backends, instead of emit_signal
@@ -164,6 +319,84 @@ pub fn new_session(
     Ok(entry_hash_round_zero)
 }
 
+/// Starts a new GameSession with the same players and game params as the finished
+/// session provided, and links the finished session to the new one.
+/// Like the first session, rematch can only be started by the host of the lobby,
+/// and every session can have only one rematch.
+pub fn rematch(finished_session_hash: EntryHash) -> ExternResult<EntryHash> {
+    let (original_hash, finished_session) = get_original_and_latest_session(finished_session_hash)?;
+    if finished_session.status == SessionState::InProgress {
+        return Err(Error::GameInProgress.into());
+    }
+    let rematch_links = get_links(original_hash.clone(), Some(LinkTag::new(REMATCH_TAG)))?;
+    if !rematch_links.into_inner().is_empty() {
+        return Err(Error::RematchAlreadyStarted.into());
+    }
+    let lobby = match get_lobby_for_anchor(finished_session.anchor.clone())? {
+        Some((_, lobby)) => lobby,
+        None => return Err(Error::LobbyNotFound.into()),
+    };
+    if lobby.host != agent_info()?.agent_initial_pubkey {
+        return Err(Error::NotTheHost.into());
+    }
+    let lobby_entry_hash = hash_entry(&lobby)?;
+    // pointing back to the finished session is what makes the rematch a new entry:
+    // everything else is the same as in the finished session
+    let round_zero_entry_hash = new_session(
+        finished_session.players,
+        finished_session.game_params,
+        finished_session.anchor,
+        lobby_entry_hash,
+        None,
+        finished_session.teams,
+        Some(original_hash.clone()),
+    )?;
+    let round_zero = must_get_entry_struct::<GameRound>(round_zero_entry_hash.clone())?;
+    create_link(
+        original_hash,
+        round_zero.session.clone(),
+        LinkTag::new(REMATCH_TAG),
+    )?;
+    // other players get StartGame from new_session, but the host asked for the rematch
+    // from the results screen, so their UI has to be told about the new session too
+    emit_signal(GameSignal::StartGame(SignalPayload {
+        game_session_entry_hash: round_zero.session,
+        round_entry_hash_update: round_zero_entry_hash.clone(),
+    }))?;
+    Ok(round_zero_entry_hash)
+}
+
+/// Takes hash of any version of the session and returns hash of its original entry,
+/// which is the one rounds and moves refer to, together with the latest session version
+pub fn get_original_and_latest_session(
+    session_hash: EntryHash,
+) -> ExternResult<(EntryHash, GameSession)> {
+    let mut original_hash = session_hash;
+    loop {
+        let details = match get_details(original_hash.clone(), GetOptions::latest())? {
+            Some(Details::Entry(details)) => details,
+            _ => return Err(Error::GameNotFound.into()),
+        };
+        let prev_hash = details.headers.iter().find_map(|h| match h.header() {
+            Header::Update(update) => Some(update.original_entry_address.clone()),
+            _ => None,
+        });
+        match prev_hash {
+            Some(prev_hash) => original_hash = prev_hash,
+            None => break,
+        }
+    }
+    let mut latest_hash = original_hash.clone();
+    while let Some((_, update_hash)) = get_canonical_update(latest_hash.clone())? {
+        latest_hash = update_hash;
+    }
+    let latest_session = match get(latest_hash, GetOptions::latest())? {
+        Some(element) => entry_from_element_create_or_update::<GameSession>(&element)?,
+        None => return Err(Error::GameNotFound.into()),
+    };
+    Ok((original_hash, latest_session))
+}
+
 fn others(players: Vec<AgentPubKey>) -> Result<Vec<AgentPubKey>, WasmError> {
     let me = &agent_info()?.agent_initial_pubkey;
     let others:Vec<AgentPubKey> = players.into_iter().filter(|p| p.ne(me)).collect();
@@ -202,7 +435,9 @@ pub fn end_game(
     game_session_header_hash: &HeaderHash,
     last_round: &GameRound,
     last_round_entry_hash: &EntryHash,
+    last_round_header_hash: &HeaderHash,
     round_state: &RoundState,
+    last_round_moves: Vec<EntryHash>,
 ) -> ExternResult<EntryHash> {
     info!("ending game");
     // last_round contains end results
@@ -212,23 +447,20 @@ pub fn end_game(
     // based on that content it can be derive if the game has ended or not
 
     info!("updating game session: setting finished state and adding player stats");
-    let game_status = if round_state.resources_taken <= 0 {
-        SessionState::Lost {
+    let game_status = match GameOutcome::from_round_state(round_state) {
+        GameOutcome::Lost => SessionState::Lost {
             last_round: last_round_entry_hash.clone(),
-        }
-    } else {
-        SessionState::Finished {
+        },
+        GameOutcome::Finished => SessionState::Finished {
             last_round: last_round_entry_hash.clone(),
-        }
+        },
     };
     //update chain for game session entry
     let game_session_update = GameSession {
-        owner: game_session.owner.clone(),
         status: game_status,
-        game_params: game_session.game_params.clone(),
-        players: game_session.players.clone(),
         scores: round_state.player_stats.clone(),
-        anchor: game_session.anchor.clone(),
+        team_scores: round_state.team_stats.clone(),
+        ..game_session.clone()
     };
     let game_session_header_hash_update =
         update_entry(game_session_header_hash.clone(), &game_session_update)?;
@@ -242,6 +474,32 @@ pub fn end_game(
         game_session_entry_hash_update.clone()
     );
 
+    // only games that were played to the end count for the leaderboards
+    if let SessionState::Finished { .. } = game_session_update.status {
+        info!("posting game scores to the leaderboards");
+        post_game_scores(
+            game_session_update.clone(),
+            game_session_entry_hash_update.clone(),
+            last_round_header_hash.clone(),
+            last_round_moves,
+        )?;
+    }
+
+    if let Some(tournament_match) = &game_session.tournament {
+        info!("starting next tournament stage if every game of this one has ended");
+        // the game is over either way, and the organizer can still start the stage by hand.
+        // If we aren't the organizer, this only signals them that the game has ended
+        let started = start_next_stage_if_ready(
+            &tournament_match.tournament,
+            tournament_match.stage,
+            Some((&last_round.session, &game_session_entry_hash_update)),
+            game_session.lobby.clone(),
+        );
+        if let Err(e) = started {
+            error!("couldn't start next tournament stage: {:?}", e);
+        }
+    }
+
     info!("signaling player game has ended");
     let signal_payload = SignalPayload {
         game_session_entry_hash: game_session_entry_hash_update.clone(),
@@ -256,6 +514,146 @@ pub fn end_game(
     Ok(game_session_entry_hash_update.clone())
 }
 
+pub fn validate_create_entry_game_session(
+    data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    let game_session: GameSession = entry_from_element_create_or_update(&data.element)?;
+    if let Err(reason) = game_session
+        .game_params
+        .check_player_count(game_session.players.len())
+    {
+        return Ok(ValidateCallbackResult::Invalid(format!(
+            "Can't create GameSession: {}",
+            reason
+        )));
+    }
+    if let Err(reason) = game_session.game_params.check_round_length() {
+        return Ok(ValidateCallbackResult::Invalid(format!(
+            "Can't create GameSession: {}",
+            reason
+        )));
+    }
+    if let Err(reason) = game_session.game_params.check_bots() {
+        return Ok(ValidateCallbackResult::Invalid(format!(
+            "Can't create GameSession: {}",
+            reason
+        )));
+    }
+    if let Err(reason) = check_teams(&game_session.players, &game_session.teams) {
+        return Ok(ValidateCallbackResult::Invalid(format!(
+            "Can't create GameSession: {}",
+            reason
+        )));
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/// Validates the link from the game code anchor to the game session: only the host
+/// of the lobby that the session was started from can create it, unless the session
+/// belongs to a tournament, whose players start its next stage themselves
+pub fn validate_create_link_game_session(
+    data: &ValidateCreateLinkData,
+) -> ExternResult<ValidateLinkCallbackResult> {
+    let game_session = match GameSession::try_from(data.target.clone()) {
+        Ok(game_session) => game_session,
+        Err(_) => {
+            return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+                "GAME_SESSION link has to target a GameSession entry",
+            )))
+        }
+    };
+    if data.link_add.base_address != game_session.anchor {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "GAME_SESSION link has to start from the anchor of the GameSession",
+        )));
+    }
+    if let Some(tournament_match) = &game_session.tournament {
+        if data.link_add.author != game_session.owner {
+            return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+                "Only the owner of the GameSession can start it",
+            )));
+        }
+        return match validate_tournament_session(
+            &game_session,
+            tournament_match,
+            &data.link_add.author,
+        )? {
+            Some(reason) => Ok(ValidateLinkCallbackResult::Invalid(reason)),
+            None => Ok(ValidateLinkCallbackResult::Valid),
+        };
+    }
+    let lobby = must_get_entry_struct::<GameLobby>(game_session.lobby.clone())?;
+    // every game code has a single lobby, see validate_create_entry_game_lobby, so it
+    // has to be the lobby of the game code the session is started for
+    if calculate_game_code_anchor_entry_hash(lobby.game_code.clone())? != data.link_add.base_address
+    {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "GameSession has to be started from the lobby of its game code",
+        )));
+    }
+    if data.link_add.author != lobby.host || game_session.owner != lobby.host {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "Only the host of the lobby can start the GameSession",
+        )));
+    }
+    Ok(ValidateLinkCallbackResult::Valid)
+}
+
+/// Validates PLAYER_SESSION link: only the session owner can link players to the session
+pub fn validate_create_link_player_session(
+    data: &ValidateCreateLinkData,
+) -> ExternResult<ValidateLinkCallbackResult> {
+    let game_session = match GameSession::try_from(data.target.clone()) {
+        Ok(game_session) => game_session,
+        Err(_) => {
+            return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+                "PLAYER_SESSION link has to target a GameSession entry",
+            )))
+        }
+    };
+    if data.link_add.author != game_session.owner {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "Only the owner of the GameSession can link players to it",
+        )));
+    }
+    let is_player = game_session
+        .players
+        .iter()
+        .any(|player| EntryHash::from(player.clone()) == data.link_add.base_address);
+    if !is_player {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "PLAYER_SESSION link has to start from one of the GameSession players",
+        )));
+    }
+    Ok(ValidateLinkCallbackResult::Valid)
+}
+
+/// Validates REMATCH link: it has to connect the finished session with the session
+/// that was started as its rematch, by the owner of the rematch
+pub fn validate_create_link_rematch(
+    data: &ValidateCreateLinkData,
+) -> ExternResult<ValidateLinkCallbackResult> {
+    let game_session = match GameSession::try_from(data.target.clone()) {
+        Ok(game_session) => game_session,
+        Err(_) => {
+            return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+                "REMATCH link has to target a GameSession entry",
+            )))
+        }
+    };
+    if game_session.previous_session.as_ref() != Some(&data.link_add.base_address) {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "REMATCH link has to start from the session the rematch was started for",
+        )));
+    }
+    if data.link_add.author != game_session.owner {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "Only the owner of the rematch can link it",
+        )));
+    }
+    Ok(ValidateLinkCallbackResult::Valid)
+}
+
 // TODO: when validating things, check that last game round is finished to verify
 // that session itself is finished
 
@@ -263,7 +661,9 @@ pub fn end_game(
 #[serde(tag = "signal_name", content = "signal_payload")]
 pub enum GameSignal {
     PlayerJoined(PlayerProfile),
+    PlayerReady(PlayerReadyPayload),
     StartGame(SignalPayload),
     StartNextRound(SignalPayload),
     GameOver(SignalPayload),
+    StageGameEnded(StageGameEndedPayload),
 }
\ No newline at end of file
@@ -0,0 +1,348 @@
+use crate::agent_profile::get_agent_profile;
+use crate::game_lobby::GameLobby;
+use crate::game_round::{calculate_round_state, must_get_closing_moves, GameRound, RoundState};
+use crate::game_session::{team_scores, GameScores, GameSession, SessionState};
+use crate::types::{PlayerStats, ResourceAmount};
+use crate::utils::{entry_from_element_create_or_update, must_get_entry_struct};
+use hdk::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+pub const LEADERBOARD_ANCHOR: &str = "LEADERBOARD";
+pub const LEADERBOARD_SCORES_TAG: &str = "LEADERBOARD_SCORES";
+const GLOBAL_LEADERBOARD: &str = "GLOBAL";
+const DEFAULT_PAGE_SIZE: usize = 20;
+
+/// Metric to sort the leaderboard by, from the biggest value to the smallest
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum LeaderboardMetric {
+    TotalHarvest,
+    CommonsSurvived,
+    GamesPlayed,
+}
+
+/// Struct to receive user input from the UI when requesting a page of the leaderboard.
+/// Global leaderboard is returned if group is None
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct LeaderboardInput {
+    pub group: Option<String>,
+    pub sort_by: LeaderboardMetric,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+/// Scores of a single agent summed up over all the games posted to the leaderboard
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    pub agent: AgentPubKey,
+    pub nickname: Option<String>,
+    pub games_played: usize,
+    pub commons_survived: usize,
+    pub total_harvest: ResourceAmount,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct LeaderboardPage {
+    pub entries: Vec<LeaderboardEntry>,
+    /// Number of agents on the whole leaderboard, to know how many pages there are
+    pub total: usize,
+}
+
+/// Anchor text of the global leaderboard or the leaderboard of the group
+fn leaderboard_anchor_text(group: &Option<String>) -> String {
+    match group {
+        Some(group) => format!("GROUP:{}", group),
+        None => String::from(GLOBAL_LEADERBOARD),
+    }
+}
+
+fn leaderboard_anchor_hash(group: &Option<String>) -> ExternResult<EntryHash> {
+    let path: Path = (&Anchor {
+        anchor_type: LEADERBOARD_ANCHOR.into(),
+        anchor_text: Some(leaderboard_anchor_text(group)),
+    })
+        .into();
+    path.hash()
+}
+
+/// Headers of every version of the round entry, from round zero up to the version with
+/// the given header. They are found by following the updates back, so forks of the
+/// round chain don't matter
+pub fn get_round_headers(last_round_header_hash: HeaderHash) -> ExternResult<Vec<HeaderHash>> {
+    let mut round_headers = vec![last_round_header_hash.clone()];
+    let mut header_hash = last_round_header_hash;
+    while let Header::Update(update) = must_get_header(header_hash.clone())?.header() {
+        header_hash = update.original_header_address.clone();
+        round_headers.push(header_hash.clone());
+    }
+    round_headers.reverse();
+    Ok(round_headers)
+}
+
+/// Derives the final state of the ended session and the harvest of every player over all
+/// its rounds, from the round headers and the moves of the last round that GameScores carry.
+/// Everything is fetched by hash, so every validator derives the same scores.
+/// Returns the reason if the rounds or the moves don't belong to the session
+pub fn derive_game_scores(
+    game_session: &GameSession,
+    round_headers: &[HeaderHash],
+    last_round_moves: &[EntryHash],
+) -> ExternResult<Result<(RoundState, PlayerStats), String>> {
+    let last_round_hash = match last_round_of(game_session) {
+        Some(last_round) => last_round,
+        None => return Ok(Err(String::from("GameSession hasn't ended yet"))),
+    };
+    let mut scores = PlayerStats::new();
+    let mut previous: Option<(HeaderHash, EntryHash, GameRound)> = None;
+    for header_hash in round_headers {
+        let header = must_get_header(header_hash.clone())?;
+        let round_hash = match header.header().entry_hash() {
+            Some(round_hash) => round_hash.clone(),
+            None => return Ok(Err(String::from("rounds have to be GameRound entries"))),
+        };
+        let round = must_get_entry_struct::<GameRound>(round_hash.clone())?;
+        // every round after round zero is an update of the one before it
+        let follows = match (&previous, header.header()) {
+            (None, Header::Create(_)) => round.round_num == 0,
+            (Some((prev_header_hash, _, prev_round)), Header::Update(update)) => {
+                &update.original_header_address == prev_header_hash
+                    && round.round_num == prev_round.round_num + 1
+                    && round.session == prev_round.session
+            }
+            _ => false,
+        };
+        if !follows {
+            return Ok(Err(String::from(
+                "rounds have to follow each other from round zero",
+            )));
+        }
+        for (player, resources) in round.state.player_stats.iter() {
+            *scores.entry(player.clone()).or_insert(0) += resources;
+        }
+        previous = Some((header_hash.clone(), round_hash, round));
+    }
+    let last_round = match previous {
+        Some((_, round_hash, round)) if &round_hash == last_round_hash => round,
+        _ => {
+            return Ok(Err(String::from(
+                "rounds have to end with the last round of the GameSession",
+            )))
+        }
+    };
+    // the last round is never updated with the state that followed it,
+    // so that state is calculated from its moves, just like end_game did
+    let moves = match must_get_closing_moves(last_round_hash, last_round_moves, game_session)? {
+        Ok(moves) => moves,
+        Err(reason) => return Ok(Err(reason)),
+    };
+    let final_state = calculate_round_state(&last_round, &game_session.game_params, moves);
+    for (player, resources) in final_state.player_stats.iter() {
+        *scores.entry(player.clone()).or_insert(0) += resources;
+    }
+    Ok(Ok((final_state, scores)))
+}
+
+/// Creates GameScores for the session that has just ended and links them
+/// to the global leaderboard and to the leaderboard of the lobby's group
+pub fn post_game_scores(
+    game_session: GameSession,
+    game_session_entry_hash: EntryHash,
+    last_round_header_hash: HeaderHash,
+    last_round_moves: Vec<EntryHash>,
+) -> ExternResult<EntryHash> {
+    let lobby = must_get_entry_struct::<GameLobby>(game_session.lobby.clone())?;
+    let round_headers = get_round_headers(last_round_header_hash)?;
+    let (final_state, scores) =
+        match derive_game_scores(&game_session, &round_headers, &last_round_moves)? {
+            Ok(derived) => derived,
+            Err(reason) => return Err(crate::err(&reason)),
+        };
+    let game_scores = GameScores {
+        commons_survived: final_state.commons_survived(),
+        total_harvest: scores.values().sum(),
+        team_scores: team_scores(&scores, &game_session.teams),
+        scores,
+        group: lobby.group,
+        game_session,
+        game_session_entry_hash,
+        round_headers,
+        last_round_moves,
+    };
+    create_entry(&game_scores)?;
+    let game_scores_hash = hash_entry(&game_scores)?;
+
+    let mut leaderboards = vec![None];
+    if game_scores.group.is_some() {
+        leaderboards.push(game_scores.group.clone());
+    }
+    for group in leaderboards {
+        let anchor_hash = anchor(LEADERBOARD_ANCHOR.into(), leaderboard_anchor_text(&group))?;
+        create_link(
+            anchor_hash,
+            game_scores_hash.clone(),
+            LinkTag::new(LEADERBOARD_SCORES_TAG),
+        )?;
+    }
+    Ok(game_scores_hash)
+}
+
+/// Sums up scores of every agent over the games posted to the leaderboard and returns
+/// one page of them, sorted by the metric requested
+pub fn get_leaderboard(input: LeaderboardInput) -> ExternResult<LeaderboardPage> {
+    let anchor_hash = leaderboard_anchor_hash(&input.group)?;
+    let links = get_links(anchor_hash, Some(LinkTag::new(LEADERBOARD_SCORES_TAG)))?;
+    let mut entries: BTreeMap<AgentPubKey, LeaderboardEntry> = BTreeMap::new();
+    // every player who ends the game creates the same GameScores, but in case the end
+    // of the game was forked, its last round still tells that it's the same game
+    let mut games_counted: BTreeSet<EntryHash> = BTreeSet::new();
+    for link in links.into_inner() {
+        let game_scores: GameScores = match get(link.target, GetOptions::default())? {
+            Some(element) => entry_from_element_create_or_update(&element)?,
+            None => continue,
+        };
+        match last_round_of(&game_scores.game_session) {
+            Some(last_round) if games_counted.insert(last_round.clone()) => (),
+            _ => continue,
+        }
+        for (agent, harvest) in game_scores.scores.iter() {
+            let entry = entries
+                .entry(agent.clone())
+                .or_insert_with(|| LeaderboardEntry {
+                    agent: agent.clone(),
+                    nickname: None,
+                    games_played: 0,
+                    commons_survived: 0,
+                    total_harvest: 0,
+                });
+            entry.games_played += 1;
+            entry.total_harvest += harvest;
+            if game_scores.commons_survived {
+                entry.commons_survived += 1;
+            }
+        }
+    }
+
+    let mut entries: Vec<LeaderboardEntry> = entries.into_iter().map(|(_, e)| e).collect();
+    entries.sort_by(|a, b| match input.sort_by {
+        LeaderboardMetric::TotalHarvest => b.total_harvest.cmp(&a.total_harvest),
+        LeaderboardMetric::CommonsSurvived => b.commons_survived.cmp(&a.commons_survived),
+        LeaderboardMetric::GamesPlayed => b.games_played.cmp(&a.games_played),
+    });
+    let total = entries.len();
+    let limit = input.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    let mut page: Vec<LeaderboardEntry> =
+        entries.into_iter().skip(input.offset).take(limit).collect();
+    // nicknames are only fetched for the agents on the requested page
+    for entry in page.iter_mut() {
+        entry.nickname = get_agent_profile(entry.agent.clone())?.map(|p| p.nickname);
+    }
+    Ok(LeaderboardPage {
+        entries: page,
+        total,
+    })
+}
+
+/// Last round of the session that has ended, None if it's still in progress
+fn last_round_of(game_session: &GameSession) -> Option<&EntryHash> {
+    match &game_session.status {
+        SessionState::Finished { last_round } | SessionState::Lost { last_round } => {
+            Some(last_round)
+        }
+        SessionState::InProgress => None,
+    }
+}
+
+pub fn validate_create_entry_game_scores(
+    data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    let game_scores: GameScores = entry_from_element_create_or_update(&data.element)?;
+    // scores have to be posted for the real session, in the state it has ended with
+    let game_session =
+        must_get_entry_struct::<GameSession>(game_scores.game_session_entry_hash.clone())?;
+    if hash_entry(&game_session)? != hash_entry(&game_scores.game_session)? {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "GameScores have to contain the GameSession they were posted for",
+        )));
+    }
+    if !matches!(game_session.status, SessionState::Finished { .. }) {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "GameScores can only be posted for a Finished GameSession",
+        )));
+    }
+    if !game_session.players.contains(data.element.header().author()) {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "GameScores can only be posted by one of the players",
+        )));
+    }
+    // scores are derived from the rounds and the moves of the last one,
+    // so nobody can post harvests that weren't made in the game
+    let (final_state, scores) = match derive_game_scores(
+        &game_session,
+        &game_scores.round_headers,
+        &game_scores.last_round_moves,
+    )? {
+        Ok(derived) => derived,
+        Err(reason) => {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "Can't derive GameScores: {}",
+                reason
+            )))
+        }
+    };
+    if game_session.scores != final_state.player_stats {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "GameSession has to end with the scores of its last round",
+        )));
+    }
+    if game_scores.scores != scores {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "GameScores have to be the harvests of the players over all the rounds",
+        )));
+    }
+    if game_scores.total_harvest != game_scores.scores.values().sum::<ResourceAmount>() {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "GameScores total harvest doesn't match the scores of the players",
+        )));
+    }
+    if game_scores.team_scores != team_scores(&game_scores.scores, &game_session.teams) {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "GameScores team scores have to be the sums of the scores of the team members",
+        )));
+    }
+    if game_scores.commons_survived != final_state.commons_survived() {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "GameScores commons_survived doesn't match what is left in the commons",
+        )));
+    }
+    let lobby = must_get_entry_struct::<GameLobby>(game_session.lobby)?;
+    if game_scores.group != lobby.group {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "GameScores group has to be the group of the lobby",
+        )));
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/// Validates LEADERBOARD_SCORES link: it has to point to GameScores and start either
+/// from the global leaderboard or from the leaderboard of the GameScores group
+pub fn validate_create_link_leaderboard_scores(
+    data: &ValidateCreateLinkData,
+) -> ExternResult<ValidateLinkCallbackResult> {
+    let game_scores = match GameScores::try_from(data.target.clone()) {
+        Ok(game_scores) => game_scores,
+        Err(_) => {
+            return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+                "LEADERBOARD_SCORES link has to target a GameScores entry",
+            )))
+        }
+    };
+    let base = &data.link_add.base_address;
+    if base != &leaderboard_anchor_hash(&None)?
+        && base != &leaderboard_anchor_hash(&game_scores.group)?
+    {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "GameScores can only be posted to the global leaderboard or the one of their group",
+        )));
+    }
+    Ok(ValidateLinkCallbackResult::Valid)
+}
@@ -5,21 +5,35 @@ use tracing_subscriber::FmtSubscriber;
 
 #[allow(unused_imports)]
 use crate::{
+    agent_profile::{AgentProfile, AgentProfileInput, AGENT_PROFILE_LINK_TAG},
+    game_lobby::{
+        LobbyGameParamsInput, LobbyGroupInput, LobbyVisibilityInput, OpenGameInfo,
+        PlayerReadyInput, TransferHostInput, LOBBY_LINK_TAG, READY_LINK_TAG,
+    },
     game_move::{GameMove, GameMoveInput},
-    game_round::GameRoundInfo,
+    game_results::{GameResults, PlayerGameStats},
+    game_round::{GameHistory, GameRoundInfo, RoundStatus},
     game_session::{
         GameParams, GameSession, GameSessionInput, GameSignal, SessionState, SignalPayload,
-        OWNER_SESSION_TAG,
+        StartGameInput, GAME_CODE_TO_SESSION_TAG, OWNER_SESSION_TAG, PLAYER_SESSION_TAG,
+        REMATCH_TAG,
     },
-    player_profile::{JoinGameInfo, PlayerProfile},
+    leaderboard::{LeaderboardInput, LeaderboardPage, LEADERBOARD_SCORES_TAG},
+    player_profile::{JoinGameInfo, PlayerProfile, PLAYER_GAME_LINK_TAG},
+    session_audit::SessionVerification,
+    session_export::SessionExport,
+    tournament::{TournamentInput, TournamentStandings, TOURNAMENT_SESSION_TAG},
     utils::{convert, entry_from_element_create_or_update},
 };
+mod agent_profile;
 mod error;
 mod game_code;
+mod game_lobby;
 #[allow(unused_imports)]
 #[allow(dead_code)]
 #[allow(unused)]
 mod game_move;
+mod game_results;
 #[allow(unused_imports)]
 #[allow(dead_code)]
 #[allow(unused)]
@@ -28,7 +42,11 @@ mod game_round;
 #[allow(dead_code)]
 #[allow(unused)]
 mod game_session;
+mod leaderboard;
 mod player_profile;
+mod session_audit;
+mod session_export;
+mod tournament;
 mod types;
 mod utils;
 
@@ -39,11 +57,14 @@ pub fn err(reason: &str) -> WasmError {
 entry_defs![
     Anchor::entry_def(),
     Path::entry_def(),
+    game_lobby::GameLobby::entry_def(),
     game_session::GameSession::entry_def(),
     game_round::GameRound::entry_def(),
     game_move::GameMove::entry_def(),
     game_session::GameScores::entry_def(),
-    player_profile::PlayerProfile::entry_def()
+    tournament::Tournament::entry_def(),
+    player_profile::PlayerProfile::entry_def(),
+    agent_profile::AgentProfile::entry_def()
 ];
 
 // give unrestricted access to recv_remote_signal, which is needed for sending remote signals
@@ -82,14 +103,47 @@ fn recv_remote_signal(signal: ExternIO) -> ExternResult<()> {
     let game_signal_result: Result<GameSignal, SerializedBytesError> = signal.decode();
     //debug!("Received REMOTE signal {:#?}", sig);
     match game_signal_result {
+        Ok(GameSignal::StageGameEnded(payload)) => tournament::receive_stage_game_ended(payload),
         Ok(a) => emit_signal(a),
         Err(_) => Err(WasmError::Guest("Remote signal failed".into())),
     }
 }
 
+/// Function to call when player starts a new game: creates the game code anchor
+/// and the lobby for it, with the caller being the host of the lobby
 #[hdk_extern]
 pub fn create_game_code_anchor(short_unique_code: String) -> ExternResult<EntryHash> {
-    game_code::create_game_code_anchor(short_unique_code)
+    game_lobby::create_lobby(short_unique_code)
+}
+
+/// Function for the host to make their lobby visible (or not) in the list of open games
+#[hdk_extern]
+pub fn set_lobby_visibility(input: LobbyVisibilityInput) -> ExternResult<EntryHash> {
+    game_lobby::set_lobby_visibility(input)
+}
+
+/// Function for the host to configure the game, e.g. to hide how many rounds it lasts
+#[hdk_extern]
+pub fn set_lobby_game_params(input: LobbyGameParamsInput) -> ExternResult<EntryHash> {
+    game_lobby::set_lobby_game_params(input)
+}
+
+/// Function for the host to choose the group leaderboard the games of their lobby count for
+#[hdk_extern]
+pub fn set_lobby_group(input: LobbyGroupInput) -> ExternResult<EntryHash> {
+    game_lobby::set_lobby_group(input)
+}
+
+/// Function to pass the host role of the lobby to another player
+#[hdk_extern]
+pub fn transfer_host(input: TransferHostInput) -> ExternResult<EntryHash> {
+    game_lobby::transfer_host(input)
+}
+
+/// Function to list all public lobbies, so players can find a game without knowing its code
+#[hdk_extern]
+pub fn list_open_games(_: ()) -> ExternResult<Vec<OpenGameInfo>> {
+    game_lobby::list_open_games()
 }
 
 #[hdk_extern]
@@ -102,9 +156,53 @@ pub fn get_players_for_game_code(short_unique_code: String) -> ExternResult<Vec<
     player_profile::get_players_for_game_code(short_unique_code)
 }
 
+/// Function for the player to tell others that they are (not) ready to start the game
+#[hdk_extern]
+pub fn set_player_ready(input: PlayerReadyInput) -> ExternResult<bool> {
+    game_lobby::set_player_ready(input)
+}
+
+/// Function to create or update caller's profile that is shared by all their games
 #[hdk_extern]
-pub fn start_game_session_with_code(game_code: String) -> ExternResult<EntryHash> {
-    game_session::start_game_session_with_code(game_code)
+pub fn set_my_profile(input: AgentProfileInput) -> ExternResult<EntryHash> {
+    agent_profile::set_my_profile(input)
+}
+
+#[hdk_extern]
+pub fn get_agent_profile(agent: AgentPubKey) -> ExternResult<Option<AgentProfile>> {
+    agent_profile::get_agent_profile(agent)
+}
+
+#[hdk_extern]
+pub fn start_game_session_with_code(input: StartGameInput) -> ExternResult<EntryHash> {
+    game_session::start_game_session_with_code(input)
+}
+
+/// Function to start a new game with the same players and params
+/// once the game session provided is over
+#[hdk_extern]
+pub fn rematch(finished_session_hash: EntryHash) -> ExternResult<EntryHash> {
+    game_session::rematch(finished_session_hash)
+}
+
+/// Function for the host to start a tournament with all the players of the lobby:
+/// players are split into groups and every stage starts once the previous one has ended
+#[hdk_extern]
+pub fn create_tournament(input: TournamentInput) -> ExternResult<EntryHash> {
+    tournament::create_tournament(input)
+}
+
+/// Function to start the next stage of the tournament in case it didn't start by itself
+/// when the last game of the current stage has ended
+#[hdk_extern]
+pub fn advance_tournament(tournament_hash: EntryHash) -> ExternResult<bool> {
+    tournament::advance_tournament(tournament_hash)
+}
+
+/// Function to get the standings of the players summed up over the tournament games
+#[hdk_extern]
+pub fn get_tournament_standings(tournament_hash: EntryHash) -> ExternResult<TournamentStandings> {
+    tournament::get_tournament_standings(tournament_hash)
 }
 
 // #[hdk_extern]
@@ -146,13 +244,65 @@ pub fn make_new_move(input: GameMoveInput) -> ExternResult<HeaderHash> {
 
 /// Function to call from the UI on a regular basis to try and close the currently
 /// active GameRound. It will check the currently available GameRound state and then
-/// will close it if it's possible. If not, it will return None
+/// will close it if it's possible and the caller is the round's designated closer,
+/// or any player once the round has been open for too long.
+/// For every other player it only reads the state and never commits anything
 #[hdk_extern]
 pub fn try_to_close_round(prev_round_hash: EntryHash) -> ExternResult<GameRoundInfo> {
     // TODO: this should probably go to the game_round.rs instead
     game_round::try_to_close_round(prev_round_hash.into())
 }
 
+/// Function to check which players already made their move in the round,
+/// including the caller. Unlike try_to_close_round it never commits anything
+#[hdk_extern]
+pub fn round_status(round_hash: EntryHash) -> ExternResult<RoundStatus> {
+    game_round::round_status(round_hash)
+}
+
+/// Function to get all the rounds of the game session with the moves made in them
+#[hdk_extern]
+pub fn get_game_history(game_session_hash: EntryHash) -> ExternResult<GameHistory> {
+    game_round::get_game_history(game_session_hash)
+}
+
+/// Function to get final results of the game session: rankings and sustainability metrics
+#[hdk_extern]
+pub fn get_game_results(game_session_hash: EntryHash) -> ExternResult<GameResults> {
+    game_results::get_game_results(game_session_hash)
+}
+
+/// Function to get statistics of the agent over all the games they have played
+#[hdk_extern]
+pub fn get_player_stats(agent: AgentPubKey) -> ExternResult<PlayerGameStats> {
+    game_results::get_player_stats(agent)
+}
+
+/// Function to get a page of the global leaderboard, or the leaderboard of a group
+#[hdk_extern]
+pub fn get_leaderboard(input: LeaderboardInput) -> ExternResult<LeaderboardPage> {
+    leaderboard::get_leaderboard(input)
+}
+
+/// Function to export the whole game session in a versioned JSON format for research analysis
+#[hdk_extern]
+pub fn export_session(game_session_hash: EntryHash) -> ExternResult<SessionExport> {
+    session_export::export_session(game_session_hash)
+}
+
+/// Function to export the game session as CSV with one row per player and round
+#[hdk_extern]
+pub fn export_session_csv(game_session_hash: EntryHash) -> ExternResult<String> {
+    session_export::export_session_csv(game_session_hash)
+}
+
+/// Function to replay the game session from its moves and report anything
+/// stored that disagrees with the replay
+#[hdk_extern]
+pub fn verify_session(game_session_hash: EntryHash) -> ExternResult<SessionVerification> {
+    session_audit::verify_session(game_session_hash)
+}
+
 #[hdk_extern]
 pub fn validate(_validation_data: ValidateData) -> ExternResult<ValidateCallbackResult> {
     // Ok(ValidateCallbackResult::Invalid("computer says no")
@@ -168,6 +318,110 @@ pub fn validate_create(_validation_data: ValidateData) -> ExternResult<ValidateC
     Ok(ValidateCallbackResult::Valid)
 }
 
+#[hdk_extern]
+pub fn validate_create_link(
+    data: ValidateCreateLinkData,
+) -> ExternResult<ValidateLinkCallbackResult> {
+    if data.link_add.tag == LinkTag::new(GAME_CODE_TO_SESSION_TAG) {
+        return game_session::validate_create_link_game_session(&data);
+    }
+    if data.link_add.tag == LinkTag::new(LOBBY_LINK_TAG) {
+        return game_lobby::validate_create_link_lobby(&data);
+    }
+    if data.link_add.tag == LinkTag::new(READY_LINK_TAG) {
+        return game_lobby::validate_create_link_ready(&data);
+    }
+    if data.link_add.tag == LinkTag::new(AGENT_PROFILE_LINK_TAG) {
+        return agent_profile::validate_create_link_agent_profile(&data);
+    }
+    if data.link_add.tag == LinkTag::new(PLAYER_GAME_LINK_TAG) {
+        return player_profile::validate_create_link_player_game(&data);
+    }
+    if data.link_add.tag == LinkTag::new(PLAYER_SESSION_TAG) {
+        return game_session::validate_create_link_player_session(&data);
+    }
+    if data.link_add.tag == LinkTag::new(REMATCH_TAG) {
+        return game_session::validate_create_link_rematch(&data);
+    }
+    if data.link_add.tag == LinkTag::new(LEADERBOARD_SCORES_TAG) {
+        return leaderboard::validate_create_link_leaderboard_scores(&data);
+    }
+    if data.link_add.tag == LinkTag::new(TOURNAMENT_SESSION_TAG) {
+        return tournament::validate_create_link_tournament_session(&data);
+    }
+    Ok(ValidateLinkCallbackResult::Valid)
+}
+
+#[hdk_extern]
+pub fn validate_delete_link(
+    data: ValidateDeleteLinkData,
+) -> ExternResult<ValidateLinkCallbackResult> {
+    // links can only be deleted by the agents who created them
+    let create_link = must_get_header(data.delete_link.link_add_address.clone())?;
+    if create_link.header().author() != &data.delete_link.author {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "Links can only be deleted by their author",
+        )));
+    }
+    Ok(ValidateLinkCallbackResult::Valid)
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_game_lobby(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    game_lobby::validate_create_entry_game_lobby(validate_data)
+}
+
+#[hdk_extern]
+pub fn validate_update_entry_game_lobby(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    game_lobby::validate_update_entry_game_lobby(validate_data)
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_player_profile(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    player_profile::validate_create_entry_player_profile(validate_data)
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_agent_profile(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    agent_profile::validate_create_entry_agent_profile(validate_data)
+}
+
+#[hdk_extern]
+pub fn validate_update_entry_agent_profile(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    agent_profile::validate_update_entry_agent_profile(validate_data)
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_game_session(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    game_session::validate_create_entry_game_session(validate_data)
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_game_scores(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    leaderboard::validate_create_entry_game_scores(validate_data)
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_tournament(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    tournament::validate_create_entry_tournament(validate_data)
+}
+
 #[hdk_extern]
 pub fn validate_create_entry_game_move(
     validate_data: ValidateData,
@@ -1,7 +1,18 @@
-use crate::{game_code::{calculate_game_code_anchor_entry_hash, create_game_code_anchor}, game_session::GameSignal};
+use crate::{
+    agent_profile::{get_agent_profile, set_my_profile, AgentProfileInput},
+    error::Error,
+    game_code::{calculate_game_code_anchor_entry_hash, create_game_code_anchor},
+    game_lobby::get_lobby_for_anchor,
+    game_session::{GameParams, GameSignal},
+    utils::entry_from_element_create_or_update,
+};
 use hdk::prelude::*;
+use std::collections::BTreeMap;
 
 pub const PLAYER_LINK_TAG: &str = "PLAYER";
+pub const PLAYER_GAME_LINK_TAG: &str = "PLAYER_GAME";
+pub const NICKNAME_MIN_LENGTH: usize = 1;
+pub const NICKNAME_MAX_LENGTH: usize = 32;
 
 /// Actual Holochain entry that stores user's profile
 /// for the specific game
@@ -78,10 +89,52 @@ Downside: More DHT ops, extra header in the DHT
 */
 pub fn join_game_with_code(input: JoinGameInfo) -> ExternResult<EntryHash> {
     info!("join_game_with_code | input: {:#?}", input);
-    let input_for_signal = input.clone();
+    // agent's durable profile defines which nickname they have in every game.
+    // Joining under another nickname renames the profile, and if agent doesn't
+    // have a profile yet, it is created with the nickname provided
+    let me = agent_info()?.agent_initial_pubkey;
+    let agent_profile = get_agent_profile(me.clone())?;
+    let nickname = match &agent_profile {
+        Some(profile) if input.nickname.is_empty() => profile.nickname.clone(),
+        _ => input.nickname.clone(),
+    };
+    check_nickname(&nickname)?;
+    let input_for_signal = JoinGameInfo {
+        nickname: nickname.clone(),
+        ..input.clone()
+    };
     let anchor = create_game_code_anchor(input.gamecode)?;
     debug!("join_game_with_code | anchor created {:#?}", &anchor);
-    let player_profile_entry_hash = create_and_hash_entry_player_profile(input.nickname)?;
+    let already_joined = get_player_profiles_for_anchor(anchor.clone())?
+        .iter()
+        .any(|p| p.player_id == me);
+    if !already_joined {
+        check_lobby_not_full(anchor.clone())?;
+    }
+    check_nickname_unique(anchor.clone(), &nickname)?;
+    match agent_profile {
+        Some(profile) if profile.nickname == nickname => (),
+        Some(profile) => {
+            set_my_profile(AgentProfileInput {
+                nickname: nickname.clone(),
+                avatar_color: profile.avatar_color,
+                bio: profile.bio,
+            })?;
+        }
+        None => {
+            set_my_profile(AgentProfileInput {
+                nickname: nickname.clone(),
+                avatar_color: String::new(),
+                bio: String::new(),
+            })?;
+        }
+    }
+    if already_joined {
+        // joining again can only rename, the agent already has a seat in the game
+        debug!("join_game_with_code | already joined");
+        return Ok(anchor);
+    }
+    let player_profile_entry_hash = create_and_hash_entry_player_profile(nickname)?;
     debug!(
         "join_game_with_code | profile entry hash {:?}",
         &player_profile_entry_hash
@@ -91,11 +144,81 @@ pub fn join_game_with_code(input: JoinGameInfo) -> ExternResult<EntryHash> {
         player_profile_entry_hash.into(),
         LinkTag::new(String::from(PLAYER_LINK_TAG)),
     )?;
+    // games of the agent are linked from their key, so renaming the profile
+    // can check that the new nickname is free in all of them
+    create_link(
+        me.into(),
+        anchor.clone(),
+        LinkTag::new(String::from(PLAYER_GAME_LINK_TAG)),
+    )?;
     debug!("join_game_with_code | link created");
     send_signal_player_joined(input_for_signal)?;
     Ok(anchor) // or more Rust like: anchor.into())
 }
 
+/// Checks that nickname has allowed length and only consists of allowed characters
+pub fn check_nickname(nickname: &str) -> Result<(), Error> {
+    let length = nickname.chars().count();
+    if length < NICKNAME_MIN_LENGTH || length > NICKNAME_MAX_LENGTH {
+        return Err(Error::InvalidNickname(format!(
+            "nickname has to be {} to {} characters long",
+            NICKNAME_MIN_LENGTH, NICKNAME_MAX_LENGTH
+        )));
+    }
+    if nickname.trim() != nickname {
+        return Err(Error::InvalidNickname(String::from(
+            "nickname can't start or end with whitespace",
+        )));
+    }
+    let allowed = |c: char| c.is_alphanumeric() || c == ' ' || c == '_' || c == '-' || c == '.';
+    if !nickname.chars().all(allowed) {
+        return Err(Error::InvalidNickname(String::from(
+            "nickname can only have letters, digits, spaces and _ - . characters",
+        )));
+    }
+    Ok(())
+}
+
+/// Makes sure that nobody else in the lobby already plays under this nickname.
+/// Nicknames are compared case-insensitively, so "Bob" and "bob" are the same nickname
+pub fn check_nickname_unique(anchor: EntryHash, nickname: &str) -> ExternResult<()> {
+    let me = agent_info()?.agent_initial_pubkey;
+    let mut players = get_player_profiles_for_anchor(anchor)?;
+    resolve_agent_nicknames(&mut players)?;
+    let taken = players
+        .iter()
+        .any(|p| p.player_id != me && p.nickname.to_lowercase() == nickname.to_lowercase());
+    if taken {
+        return Err(Error::NicknameTaken(nickname.to_string()).into());
+    }
+    Ok(())
+}
+
+/// Retrieves game code anchors of all the games the agent has joined
+pub fn get_games_of_agent(agent: AgentPubKey) -> ExternResult<Vec<EntryHash>> {
+    let links = get_links(
+        agent.into(),
+        Some(LinkTag::new(String::from(PLAYER_GAME_LINK_TAG))),
+    )?;
+    let mut anchors: Vec<EntryHash> = links.into_inner().into_iter().map(|l| l.target).collect();
+    anchors.sort();
+    anchors.dedup();
+    Ok(anchors)
+}
+
+/// Makes sure that there is still a free seat in the lobby for an agent joining it
+fn check_lobby_not_full(anchor: EntryHash) -> ExternResult<()> {
+    let game_params = match get_lobby_for_anchor(anchor.clone())? {
+        Some((_, lobby)) => lobby.game_params,
+        None => GameParams::default(),
+    };
+    let players = get_player_profiles_for_anchor(anchor)?;
+    if players.len() >= game_params.human_seats() as usize {
+        return Err(Error::LobbyFull(game_params.human_seats()).into());
+    }
+    Ok(())
+}
+
 fn send_signal_player_joined(input: JoinGameInfo) -> ExternResult<()> {
     let p = PlayerProfile {
         player_id: agent_info()?.agent_initial_pubkey, // bad design for real apps 1/ initial_pubkey is linked to app itself, so no roaming profile 2/ lost if app is reinstalled (= basicly new user)
@@ -112,10 +235,16 @@ pub fn get_player_profiles_for_game_code(
     short_unique_code: String,
 ) -> ExternResult<Vec<PlayerProfile>> {
     let anchor = calculate_game_code_anchor_entry_hash(short_unique_code)?;
+    get_player_profiles_for_anchor(anchor)
+}
+
+/// Retrieves profiles of all the players who joined the game. Every agent is in the list
+/// once, even if they happened to join more than once at the same time
+pub fn get_player_profiles_for_anchor(anchor: EntryHash) -> ExternResult<Vec<PlayerProfile>> {
     debug!("anchor: {:?}", anchor);
     let links: Links = get_links(anchor, Some(LinkTag::new(String::from(PLAYER_LINK_TAG))))?;
     debug!("links: {:#?}", links);
-    let mut players = vec![];
+    let mut players: Vec<PlayerProfile> = vec![];
     for link in links.into_inner() {
         debug!("link: {:#?}", link);
         let element: Element = get(link.target, GetOptions::default())?
@@ -124,17 +253,41 @@ pub fn get_player_profiles_for_game_code(
         let entry: PlayerProfile = entry_option.ok_or(WasmError::Guest(
             "The targeted entry is not agent pubkey".into(),
         ))?;
-        players.push(entry);
+        if !players.iter().any(|p| p.player_id == entry.player_id) {
+            players.push(entry);
+        }
     }
 
     Ok(players) // or more Rust like: anchor.into())
 }
 
+/// Replaces nicknames that players had at the moment of joining the game
+/// with the nicknames from their durable profiles
+pub fn resolve_agent_nicknames(player_profiles: &mut Vec<PlayerProfile>) -> ExternResult<()> {
+    for player_profile in player_profiles.iter_mut() {
+        if let Some(profile) = get_agent_profile(player_profile.player_id.clone())? {
+            player_profile.nickname = profile.nickname;
+        }
+    }
+    Ok(())
+}
+
+/// Retrieves nicknames of all the players of the game, keyed by their pub keys
+pub fn get_nicknames_for_anchor(anchor: EntryHash) -> ExternResult<BTreeMap<AgentPubKey, String>> {
+    let mut player_profiles = get_player_profiles_for_anchor(anchor)?;
+    resolve_agent_nicknames(&mut player_profiles)?;
+    Ok(player_profiles
+        .into_iter()
+        .map(|p| (p.player_id, p.nickname))
+        .collect())
+}
+
 pub fn get_players_for_game_code(short_unique_code: String) -> ExternResult<Vec<PlayerProfile>> {
     // Ok(vec!["Anipur".into(), "Bob".into()]);
 
     debug!("get profiles");
-    let player_profiles = get_player_profiles_for_game_code(short_unique_code)?;
+    let mut player_profiles = get_player_profiles_for_game_code(short_unique_code)?;
+    resolve_agent_nicknames(&mut player_profiles)?;
 
     // debug!("filter profiles to extract nickname");
     let players: Vec<String> = player_profiles.iter().map(|x| x.nickname.clone()).collect();
@@ -142,3 +295,26 @@ pub fn get_players_for_game_code(short_unique_code: String) -> ExternResult<Vec<
     debug!("profiles {:#?}", player_profiles);
     Ok(player_profiles) // or more Rust like: anchor.into())
 }
+
+pub fn validate_create_entry_player_profile(
+    data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    let player_profile: PlayerProfile = entry_from_element_create_or_update(&data.element)?;
+    if let Err(e) = check_nickname(&player_profile.nickname) {
+        return e.into();
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/// Validates PLAYER_GAME link: agents can only link games to their own key
+pub fn validate_create_link_player_game(
+    data: &ValidateCreateLinkData,
+) -> ExternResult<ValidateLinkCallbackResult> {
+    let author: EntryHash = data.link_add.author.clone().into();
+    if data.link_add.base_address != author {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "Agents can only link games they join to themselves",
+        )));
+    }
+    Ok(ValidateLinkCallbackResult::Valid)
+}
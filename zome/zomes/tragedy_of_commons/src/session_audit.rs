@@ -0,0 +1,184 @@
+use crate::error::Error;
+use crate::game_move::{finalize_moves, get_move_entries_for_round, GameMove};
+use crate::game_round::{
+    calculate_round_state, get_round_chain, start_new_round, GameRound, RoundState,
+};
+use crate::game_session::{get_original_and_latest_session, GameOutcome, SessionState};
+use crate::types::PlayerStats;
+use crate::utils::entry_from_element_create_or_update;
+use hdk::prelude::*;
+
+/// Result of replaying the game session from its moves
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct SessionVerification {
+    pub game_session_hash: EntryHash,
+    /// True if nothing disagrees with the replay
+    pub valid: bool,
+    pub rounds_checked: usize,
+    pub round_mismatches: Vec<RoundMismatch>,
+    pub scores_mismatch: Option<ScoresMismatch>,
+    /// Problems with the recorded data itself, e.g. a move that belongs to another round
+    pub problems: Vec<String>,
+}
+
+/// Round whose stored state differs from the one calculated from its moves
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoundMismatch {
+    pub round_num: u32,
+    pub stored: RoundState,
+    pub recalculated: RoundState,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScoresMismatch {
+    pub stored: PlayerStats,
+    pub recalculated: PlayerStats,
+}
+
+/// Recalculates every round of the session from round zero with calculate_round_state
+/// and reports every stored round state and final scores that disagree with it.
+/// The moves every round counts are picked again from all the moves linked to the round
+/// before, and the game has to end exactly when it runs out of rounds or resources
+pub fn verify_session(game_session_hash: EntryHash) -> ExternResult<SessionVerification> {
+    let (original_session_hash, game_session) =
+        get_original_and_latest_session(game_session_hash)?;
+    let params = &game_session.game_params;
+    let round_chain = get_round_chain(original_session_hash.clone())?;
+    let mut round_mismatches = vec![];
+    let mut problems = vec![];
+
+    let (round_zero_hash, round_zero_element) = match round_chain.first() {
+        Some(round_zero) => round_zero,
+        None => return Err(Error::RoundNotFound.into()),
+    };
+    let round_zero: GameRound = entry_from_element_create_or_update(round_zero_element)?;
+    if round_zero.state.resources_left != params.start_amount {
+        problems.push(format!(
+            "Round 0 starts with {} resources instead of {}",
+            round_zero.state.resources_left, params.start_amount
+        ));
+    }
+
+    // we keep replaying from the recalculated state, so a single broken round
+    // shows up in every round after it as well
+    let mut prev_hash = round_zero_hash.clone();
+    let mut prev_element = round_zero_element;
+    let mut prev_round = round_zero;
+    for (round_hash, element) in round_chain.iter().skip(1) {
+        let round: GameRound = entry_from_element_create_or_update(element)?;
+        let linked_moves = get_move_entries_for_round(prev_element)?;
+        match finalize_moves(linked_moves, game_session.players.len())? {
+            Some(unique_moves) => {
+                let mut selected: Vec<EntryHash> =
+                    unique_moves.into_iter().map(|(hash, _)| hash).collect();
+                let mut stored = round.previous_round_moves.clone();
+                selected.sort();
+                stored.sort();
+                if selected != stored {
+                    problems.push(format!(
+                        "Round {} doesn't count the moves picked from all the moves of round {}",
+                        round.round_num, prev_round.round_num
+                    ));
+                }
+            }
+            None => problems.push(format!(
+                "Round {} was started, but not every player moved in round {}",
+                round.round_num, prev_round.round_num
+            )),
+        }
+        let mut moves: Vec<GameMove> = vec![];
+        for move_hash in &round.previous_round_moves {
+            let game_move: GameMove = match get(move_hash.clone(), GetOptions::latest())? {
+                Some(move_element) => entry_from_element_create_or_update(&move_element)?,
+                None => return Err(Error::MoveNotFound.into()),
+            };
+            if game_move.round != prev_hash {
+                problems.push(format!(
+                    "Round {} counts move {} made in another round",
+                    round.round_num, move_hash
+                ));
+            }
+            moves.push(game_move);
+        }
+        if moves.len() != game_session.players.len() {
+            problems.push(format!(
+                "Round {} counts {} moves for {} players",
+                round.round_num,
+                moves.len(),
+                game_session.players.len()
+            ));
+        }
+        let recalculated = calculate_round_state(&prev_round, params, moves);
+        if !start_new_round(&game_session, &prev_round, &recalculated) {
+            problems.push(format!(
+                "Round {} was started, but the game should have ended after round {}",
+                round.round_num, prev_round.round_num
+            ));
+        }
+        if recalculated != round.state {
+            round_mismatches.push(RoundMismatch {
+                round_num: round.round_num,
+                stored: round.state.clone(),
+                recalculated: recalculated.clone(),
+            });
+        }
+        prev_hash = round_hash.clone();
+        prev_element = element;
+        prev_round = GameRound {
+            state: recalculated,
+            ..round
+        };
+    }
+
+    // final round never gets a successor, its results only live in the session scores
+    let mut scores_mismatch = None;
+    if game_session.status != SessionState::InProgress {
+        let (_, last_element) = round_chain.last().ok_or(Error::RoundNotFound)?;
+        let moves = get_move_entries_for_round(last_element)?;
+        match finalize_moves(moves, game_session.players.len())? {
+            Some(unique_moves) => {
+                let final_state = calculate_round_state(
+                    &prev_round,
+                    params,
+                    unique_moves.into_iter().map(|(_, m)| m).collect(),
+                );
+                if start_new_round(&game_session, &prev_round, &final_state) {
+                    problems.push(format!(
+                        "Game has ended after round {}, but it had rounds and resources left",
+                        prev_round.round_num
+                    ));
+                }
+                if final_state.player_stats != game_session.scores {
+                    scores_mismatch = Some(ScoresMismatch {
+                        stored: game_session.scores.clone(),
+                        recalculated: final_state.player_stats.clone(),
+                    });
+                }
+                let outcome = GameOutcome::from_round_state(&final_state);
+                let outcome_matches = matches!(
+                    (&game_session.status, &outcome),
+                    (SessionState::Lost { .. }, GameOutcome::Lost)
+                        | (SessionState::Finished { .. }, GameOutcome::Finished)
+                );
+                if !outcome_matches {
+                    problems.push(format!(
+                        "Game ended as {:?}, but its moves lead to {:?}",
+                        game_session.status, outcome
+                    ));
+                }
+            }
+            None => problems.push(String::from(
+                "Game has ended, but not every player moved in the last round",
+            )),
+        }
+    }
+
+    Ok(SessionVerification {
+        game_session_hash: original_session_hash,
+        valid: round_mismatches.is_empty() && scores_mismatch.is_none() && problems.is_empty(),
+        rounds_checked: round_chain.len() - 1,
+        round_mismatches,
+        scores_mismatch,
+        problems,
+    })
+}
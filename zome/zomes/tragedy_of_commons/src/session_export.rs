@@ -0,0 +1,190 @@
+use crate::error::Error;
+use crate::game_move::{finalize_moves, get_move_elements_for_round, GameMove};
+use crate::game_round::{calculate_round_state, get_round_chain, GameRound, RoundState};
+use crate::game_session::{get_original_and_latest_session, GameParams, SessionState};
+use crate::player_profile::get_nicknames_for_anchor;
+use crate::types::ResourceAmount;
+use crate::utils::entry_from_element_create_or_update;
+use hdk::prelude::*;
+
+/// Version of the export format. It has to be increased whenever
+/// fields of the export are renamed, removed or change their meaning
+pub const EXPORT_SCHEMA_VERSION: u32 = 2;
+
+/// Whole game session in a stable format for research analysis
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct SessionExport {
+    pub schema_version: u32,
+    pub game_session_hash: EntryHash,
+    pub status: SessionState,
+    pub game_params: GameParams,
+    pub players: Vec<ExportedPlayer>,
+    pub rounds: Vec<ExportedRound>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExportedPlayer {
+    pub agent: AgentPubKey,
+    pub nickname: String,
+}
+
+/// Played round: every move made in it and the state of the commons after the ones that counted
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExportedRound {
+    pub round_num: u32,
+    pub state: RoundState,
+    pub moves: Vec<ExportedMove>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExportedMove {
+    pub move_hash: EntryHash,
+    pub owner: AgentPubKey,
+    pub resources: ResourceAmount,
+    /// Team the move counted for, None unless the session was played in teams
+    #[serde(default)]
+    pub team: Option<String>,
+    /// Time the move was committed, in microseconds since the UNIX epoch
+    pub timestamp_micros: i64,
+    /// Whether the move was picked to close the round. A player that moved more than
+    /// once in the round has only one of their moves counted
+    pub counted: bool,
+}
+
+/// Collects the whole session. Walks the rounds and their moves the same way
+/// try_to_close_round does, and exports all the moves linked to every round
+/// with the ones that counted marked as such
+pub fn export_session(game_session_hash: EntryHash) -> ExternResult<SessionExport> {
+    let (original_session_hash, game_session) =
+        get_original_and_latest_session(game_session_hash)?;
+    let nicknames = get_nicknames_for_anchor(game_session.anchor.clone())?;
+    let players = game_session
+        .players
+        .iter()
+        .map(|agent| ExportedPlayer {
+            agent: agent.clone(),
+            nickname: nicknames
+                .get(agent)
+                .cloned()
+                .unwrap_or_else(|| String::from("unknown player")),
+        })
+        .collect();
+
+    let round_chain = get_round_chain(original_session_hash.clone())?;
+    let mut rounds = vec![];
+    for (index, (_, round_element)) in round_chain.iter().enumerate() {
+        let round: GameRound = entry_from_element_create_or_update(round_element)?;
+        let mut moves = vec![];
+        for (move_hash, move_element) in get_move_elements_for_round(round_element)? {
+            let game_move: GameMove = entry_from_element_create_or_update(&move_element)?;
+            moves.push((move_hash, game_move, move_element.header().timestamp()));
+        }
+        let (counted_moves, state) = match round_chain.get(index + 1) {
+            // closed round: next round knows the moves it was calculated from
+            Some((_, next_round_element)) => {
+                let next_round: GameRound =
+                    entry_from_element_create_or_update(next_round_element)?;
+                (next_round.previous_round_moves, next_round.state)
+            }
+            // last round of the finished game: pick the moves like end_game did
+            None if game_session.status != SessionState::InProgress => {
+                let entries = moves.iter().map(|(h, m, _)| (h.clone(), m.clone())).collect();
+                let unique_moves = match finalize_moves(entries, game_session.players.len())? {
+                    Some(unique_moves) => unique_moves,
+                    None => break,
+                };
+                let state = calculate_round_state(
+                    &round,
+                    &game_session.game_params,
+                    unique_moves.iter().map(|(_, m)| m.clone()).collect(),
+                );
+                let counted_moves = unique_moves.into_iter().map(|(h, _)| h).collect();
+                (counted_moves, state)
+            }
+            // round that is still being played
+            None => break,
+        };
+        rounds.push(ExportedRound {
+            round_num: round.round_num + 1,
+            state,
+            moves: moves
+                .into_iter()
+                .map(|(move_hash, game_move, timestamp)| ExportedMove {
+                    counted: counted_moves.contains(&move_hash),
+                    move_hash,
+                    owner: game_move.owner,
+                    resources: game_move.resources,
+                    team: game_move.team,
+                    timestamp_micros: timestamp.as_micros(),
+                })
+                .collect(),
+        });
+    }
+
+    Ok(SessionExport {
+        schema_version: EXPORT_SCHEMA_VERSION,
+        game_session_hash: original_session_hash,
+        status: game_session.status,
+        game_params: game_session.game_params,
+        players,
+        rounds,
+    })
+}
+
+/// Same export flattened into CSV with one row per move and round, followed by one
+/// row per bot and round. Bot rows have no player key and no move timestamp
+pub fn export_session_csv(game_session_hash: EntryHash) -> ExternResult<String> {
+    let export = export_session(game_session_hash)?;
+    let mut csv = String::from(
+        "schema_version,game_session_hash,round_num,player,nickname,is_bot,resources_taken,\
+         move_timestamp_micros,counted,round_resources_taken,round_resources_grown,\
+         resources_left\n",
+    );
+    for round in export.rounds.iter() {
+        for game_move in round.moves.iter() {
+            let nickname = export
+                .players
+                .iter()
+                .find(|p| p.agent == game_move.owner)
+                .map(|p| p.nickname.clone())
+                .ok_or(Error::NotAPlayer)?;
+            csv.push_str(&format!(
+                "{},{},{},{},{},false,{},{},{},{},{},{}\n",
+                export.schema_version,
+                export.game_session_hash,
+                round.round_num,
+                game_move.owner,
+                csv_field(&nickname),
+                game_move.resources,
+                game_move.timestamp_micros,
+                game_move.counted,
+                round.state.resources_taken,
+                round.state.resources_grown,
+                round.state.resources_left,
+            ));
+        }
+        for (bot, resources) in round.state.bot_stats.iter() {
+            csv.push_str(&format!(
+                "{},{},{},,{},true,{},,true,{},{},{}\n",
+                export.schema_version,
+                export.game_session_hash,
+                round.round_num,
+                csv_field(bot),
+                resources,
+                round.state.resources_taken,
+                round.state.resources_grown,
+                round.state.resources_left,
+            ));
+        }
+    }
+    Ok(csv)
+}
+
+/// Quotes the value if it could break the CSV row
+fn csv_field(value: &str) -> String {
+    if value.contains(|c| c == ',' || c == '"' || c == '\n') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
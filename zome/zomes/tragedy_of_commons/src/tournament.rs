@@ -0,0 +1,519 @@
+use crate::error::Error;
+use crate::game_code::calculate_game_code_anchor_entry_hash;
+use crate::game_lobby::get_lobby_for_anchor;
+use crate::game_results::get_game_results;
+use crate::game_round::GameRound;
+use crate::game_session::{
+    get_original_and_latest_session, new_session, GameParams, GameSession, GameSignal,
+    SessionState, GAME_CODE_TO_SESSION_TAG,
+};
+use crate::player_profile::{get_nicknames_for_anchor, get_player_profiles_for_anchor};
+use crate::types::ResourceAmount;
+use crate::utils::{entry_from_element_create_or_update, must_get_entry_struct};
+use hdk::prelude::*;
+use std::collections::BTreeMap;
+use tragedy_of_commons_rules::round_robin_schedule;
+
+pub const TOURNAMENT_TAG: &str = "TOURNAMENT";
+pub const TOURNAMENT_SESSION_TAG: &str = "TOURNAMENT_SESSION";
+
+/// Series of games played by the players of one lobby. Every stage splits the whole
+/// pool into groups that play their GameSessions at the same time, and the next
+/// stage starts once every session of the current one has ended
+#[hdk_entry(id = "tournament", visibility = "public")]
+#[derive(Clone)]
+pub struct Tournament {
+    pub game_code: String,
+    pub organizer: AgentPubKey,
+    pub players: Vec<AgentPubKey>,
+    pub game_params: GameParams,
+    pub group_size: u32,
+    /// Groups of players of every stage, calculated with round_robin_schedule
+    pub schedule: Vec<Vec<Vec<AgentPubKey>>>,
+}
+
+/// Stage of the tournament the GameSession is played in
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TournamentMatch {
+    pub tournament: EntryHash,
+    pub stage: u32,
+    /// Ended versions of all the sessions of the stage before, which proves
+    /// that this stage was only started once the previous one was over
+    #[serde(default)]
+    pub previous_stage: Vec<EntryHash>,
+}
+
+/// Payload of the signal a player sends the organizer when they end a game of the
+/// tournament, so that the organizer can start the next stage if it was the last one
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct StageGameEndedPayload {
+    pub tournament_hash: EntryHash,
+    pub stage: u32,
+    pub game_session_hash: EntryHash,
+    pub game_session_entry_hash_update: EntryHash,
+    pub lobby: EntryHash,
+}
+
+/// Struct to receive user input from the UI when the host starts a tournament
+/// with all the players of their lobby
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct TournamentInput {
+    pub game_code: String,
+    /// Smallest number of players in a group, some groups get one more player
+    /// if the pool can't be split evenly
+    pub group_size: u32,
+    pub stages: u32,
+}
+
+/// Scores of a single player summed up over all the tournament games they have finished
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TournamentStanding {
+    pub agent: AgentPubKey,
+    pub nickname: String,
+    /// 1 for the best player, players with equal results share the rank
+    pub rank: usize,
+    pub games_played: usize,
+    pub commons_survived: usize,
+    pub total_harvest: ResourceAmount,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct TournamentStandings {
+    pub tournament_hash: EntryHash,
+    /// Number of stages that have been started so far
+    pub stages_started: u32,
+    pub stages_total: u32,
+    pub finished: bool,
+    pub standings: Vec<TournamentStanding>,
+}
+
+/// Creates the tournament for all the players of the lobby and starts its first stage.
+/// Like a single game, only the host of the lobby can start it, and only once
+pub fn create_tournament(input: TournamentInput) -> ExternResult<EntryHash> {
+    let anchor = calculate_game_code_anchor_entry_hash(input.game_code.clone())?;
+    let lobby = match get_lobby_for_anchor(anchor.clone())? {
+        Some((_, lobby)) => lobby,
+        None => return Err(Error::LobbyNotFound.into()),
+    };
+    let me = agent_info()?.agent_initial_pubkey;
+    if lobby.host != me {
+        return Err(Error::NotTheHost.into());
+    }
+    let session_links = get_links(
+        anchor.clone(),
+        Some(LinkTag::new(GAME_CODE_TO_SESSION_TAG)),
+    )?;
+    if !session_links.into_inner().is_empty() {
+        return Err(Error::GameAlreadyStarted.into());
+    }
+    if input.stages == 0 {
+        return Err(Error::InvalidTournament(String::from(
+            "tournament has to have at least one stage",
+        ))
+        .into());
+    }
+    lobby.game_params.check_round_length().map_err(Error::from)?;
+    lobby.game_params.check_bots().map_err(Error::from)?;
+
+    let players: Vec<AgentPubKey> = get_player_profiles_for_anchor(anchor.clone())?
+        .into_iter()
+        .map(|p| p.player_id)
+        .collect();
+    let lobby_hash = hash_entry(&lobby)?;
+    let schedule = round_robin_schedule(&players, input.group_size, input.stages);
+    check_schedule(&schedule, &lobby.game_params)?;
+
+    let tournament = Tournament {
+        game_code: input.game_code,
+        organizer: me,
+        players,
+        game_params: lobby.game_params,
+        group_size: input.group_size,
+        schedule,
+    };
+    create_entry(&tournament)?;
+    let tournament_hash = hash_entry(&tournament)?;
+    create_link(anchor, tournament_hash.clone(), LinkTag::new(TOURNAMENT_TAG))?;
+
+    start_stage(&tournament, tournament_hash.clone(), 0, vec![], lobby_hash, &[])?;
+    Ok(tournament_hash)
+}
+
+/// Every group of every stage has to fit the player limits of the game params
+fn check_schedule(
+    schedule: &[Vec<Vec<AgentPubKey>>],
+    game_params: &GameParams,
+) -> Result<(), Error> {
+    for group in schedule.iter().flatten() {
+        game_params.check_player_count(group.len())?;
+    }
+    Ok(())
+}
+
+/// Starts a GameSession for every group of the stage that hasn't got one yet
+/// and links it to the tournament
+fn start_stage(
+    tournament: &Tournament,
+    tournament_hash: EntryHash,
+    stage: u32,
+    previous_stage: Vec<EntryHash>,
+    lobby_hash: EntryHash,
+    started_groups: &[Vec<AgentPubKey>],
+) -> ExternResult<()> {
+    let anchor = calculate_game_code_anchor_entry_hash(tournament.game_code.clone())?;
+    let groups = match tournament.schedule.get(stage as usize) {
+        Some(groups) => groups,
+        None => return Ok(()),
+    };
+    for group in groups.iter().filter(|g| !started_groups.contains(g)) {
+        let round_zero_hash = new_session(
+            group.clone(),
+            tournament.game_params.clone(),
+            anchor.clone(),
+            lobby_hash.clone(),
+            Some(TournamentMatch {
+                tournament: tournament_hash.clone(),
+                stage,
+                previous_stage: previous_stage.clone(),
+            }),
+            vec![],
+            None,
+        )?;
+        let round_zero = must_get_entry_struct::<GameRound>(round_zero_hash)?;
+        create_link(
+            tournament_hash.clone(),
+            round_zero.session,
+            LinkTag::new(TOURNAMENT_SESSION_TAG),
+        )?;
+    }
+    Ok(())
+}
+
+/// Original hashes and latest versions of all the sessions of the tournament started so far
+fn get_tournament_sessions(
+    tournament_hash: EntryHash,
+) -> ExternResult<Vec<(EntryHash, GameSession)>> {
+    let links = get_links(tournament_hash, Some(LinkTag::new(TOURNAMENT_SESSION_TAG)))?;
+    // a session started twice is the same entry, so it's only counted once
+    let mut sessions = BTreeMap::new();
+    for link in links.into_inner() {
+        let (original_hash, session) = get_original_and_latest_session(link.target)?;
+        sessions.insert(original_hash, session);
+    }
+    Ok(sessions.into_iter().collect())
+}
+
+/// Starts the next stage of the tournament if every session of the stage has ended.
+/// Only the organizer starts stages, so every session of a stage is the same entry
+/// however many times it gets started. end_game passes the session that has just
+/// ended with the hash of its ended version, since get might not see it as ended yet.
+/// Any other player who ends a game signals it to the organizer instead, whose node
+/// then starts the stage in receive_stage_game_ended
+pub fn start_next_stage_if_ready(
+    tournament_hash: &EntryHash,
+    stage: u32,
+    just_ended: Option<(&EntryHash, &EntryHash)>,
+    lobby_hash: EntryHash,
+) -> ExternResult<bool> {
+    let tournament = must_get_entry_struct::<Tournament>(tournament_hash.clone())?;
+    if agent_info()?.agent_initial_pubkey != tournament.organizer {
+        if let Some((game_session_hash, game_session_entry_hash_update)) = just_ended {
+            let signal = ExternIO::encode(GameSignal::StageGameEnded(StageGameEndedPayload {
+                tournament_hash: tournament_hash.clone(),
+                stage,
+                game_session_hash: game_session_hash.clone(),
+                game_session_entry_hash_update: game_session_entry_hash_update.clone(),
+                lobby: lobby_hash,
+            }))?;
+            remote_signal(signal, vec![tournament.organizer])?;
+        }
+        return Ok(false);
+    }
+    let next_stage = stage + 1;
+    let next_groups = match tournament.schedule.get(next_stage as usize) {
+        Some(groups) => groups,
+        None => return Ok(false),
+    };
+    let sessions = get_tournament_sessions(tournament_hash.clone())?;
+    let stage_of = |session: &GameSession| session.tournament.as_ref().map(|m| m.stage);
+    // a stage that was started only partly gets its missing groups started
+    let started_groups: Vec<Vec<AgentPubKey>> = sessions
+        .iter()
+        .filter(|(_, s)| stage_of(s) == Some(next_stage))
+        .map(|(_, s)| s.players.clone())
+        .collect();
+    if next_groups.iter().all(|g| started_groups.contains(g)) {
+        return Ok(false);
+    }
+    let mut ended_sessions = vec![];
+    for (original_hash, session) in sessions.iter().filter(|(_, s)| stage_of(s) == Some(stage)) {
+        match just_ended {
+            Some((ended_hash, ended_version)) if ended_hash == original_hash => {
+                ended_sessions.push(ended_version.clone())
+            }
+            _ if session.status != SessionState::InProgress => {
+                ended_sessions.push(hash_entry(session)?)
+            }
+            _ => return Ok(false),
+        }
+    }
+    if ended_sessions.len() != tournament.schedule[stage as usize].len() {
+        return Ok(false);
+    }
+    ended_sessions.sort();
+    start_stage(
+        &tournament,
+        tournament_hash.clone(),
+        next_stage,
+        ended_sessions,
+        lobby_hash,
+        &started_groups,
+    )?;
+    Ok(true)
+}
+
+/// Handles the signal of a player who has ended a game of the tournament. The organizer
+/// starts the next stage if that game was the last one of its stage, and the UI gets
+/// the signal either way, so the organizer can still start the stage by hand
+pub fn receive_stage_game_ended(payload: StageGameEndedPayload) -> ExternResult<()> {
+    let tournament = must_get_entry_struct::<Tournament>(payload.tournament_hash.clone())?;
+    if agent_info()?.agent_initial_pubkey == tournament.organizer {
+        let started = start_next_stage_if_ready(
+            &payload.tournament_hash,
+            payload.stage,
+            Some((
+                &payload.game_session_hash,
+                &payload.game_session_entry_hash_update,
+            )),
+            payload.lobby.clone(),
+        );
+        if let Err(e) = started {
+            error!("couldn't start next tournament stage: {:?}", e);
+        }
+    }
+    emit_signal(GameSignal::StageGameEnded(payload))
+}
+
+/// Lets the organizer start the next stage by hand, in case the signal of the last
+/// session of the stage got lost or the organizer didn't see every session ended yet
+pub fn advance_tournament(tournament_hash: EntryHash) -> ExternResult<bool> {
+    let tournament = must_get_entry_struct::<Tournament>(tournament_hash.clone())?;
+    if agent_info()?.agent_initial_pubkey != tournament.organizer {
+        return Err(Error::NotTheHost.into());
+    }
+    let sessions = get_tournament_sessions(tournament_hash.clone())?;
+    // the whole latest stage gets checked, so any of its sessions will do
+    let latest = sessions
+        .iter()
+        .filter_map(|(_, s)| s.tournament.as_ref().map(|m| (m.stage, s)))
+        .max_by_key(|(stage, _)| *stage);
+    let (stage, session) = match latest {
+        Some(latest) => latest,
+        None => return Ok(false),
+    };
+    start_next_stage_if_ready(&tournament_hash, stage, None, session.lobby.clone())
+}
+
+/// Sums up scores of every finished session of the tournament. Players are ranked by
+/// the number of games where their group kept the commons alive, then by their harvest
+pub fn get_tournament_standings(tournament_hash: EntryHash) -> ExternResult<TournamentStandings> {
+    let tournament = must_get_entry_struct::<Tournament>(tournament_hash.clone())?;
+    let anchor = calculate_game_code_anchor_entry_hash(tournament.game_code.clone())?;
+    let nicknames = get_nicknames_for_anchor(anchor)?;
+    let mut standings: BTreeMap<AgentPubKey, TournamentStanding> = tournament
+        .players
+        .iter()
+        .map(|agent| {
+            let standing = TournamentStanding {
+                agent: agent.clone(),
+                nickname: nicknames
+                    .get(agent)
+                    .cloned()
+                    .unwrap_or_else(|| String::from("unknown player")),
+                rank: 0,
+                games_played: 0,
+                commons_survived: 0,
+                total_harvest: 0,
+            };
+            (agent.clone(), standing)
+        })
+        .collect();
+
+    let sessions = get_tournament_sessions(tournament_hash.clone())?;
+    let mut stages_started = 0;
+    let mut all_ended = true;
+    for (original_hash, session) in sessions.iter() {
+        if let Some(m) = &session.tournament {
+            stages_started = stages_started.max(m.stage + 1);
+        }
+        if session.status == SessionState::InProgress {
+            all_ended = false;
+            continue;
+        }
+        let results = get_game_results(original_hash.clone())?;
+        for player in results.players {
+            if let Some(standing) = standings.get_mut(&player.player) {
+                standing.games_played += 1;
+                standing.total_harvest += player.harvest;
+                if results.commons_survived {
+                    standing.commons_survived += 1;
+                }
+            }
+        }
+    }
+
+    let mut standings: Vec<TournamentStanding> = standings.into_iter().map(|(_, s)| s).collect();
+    let results: Vec<(usize, ResourceAmount)> = standings
+        .iter()
+        .map(|s| (s.commons_survived, s.total_harvest))
+        .collect();
+    for standing in standings.iter_mut() {
+        let result = (standing.commons_survived, standing.total_harvest);
+        standing.rank = 1 + results.iter().filter(|r| **r > result).count();
+    }
+    standings.sort_by_key(|s| s.rank);
+
+    let stages_total = tournament.schedule.len() as u32;
+    Ok(TournamentStandings {
+        tournament_hash,
+        stages_started,
+        stages_total,
+        finished: all_ended && stages_started == stages_total,
+        standings,
+    })
+}
+
+pub fn validate_create_entry_tournament(
+    data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    let tournament: Tournament = entry_from_element_create_or_update(&data.element)?;
+    if &tournament.organizer != data.element.header().author() {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "Tournament can only be created with its author as the organizer",
+        )));
+    }
+    let stages = tournament.schedule.len() as u32;
+    if stages == 0 {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "Tournament has to have at least one stage",
+        )));
+    }
+    let schedule = round_robin_schedule(&tournament.players, tournament.group_size, stages);
+    if tournament.schedule != schedule {
+        return Ok(ValidateCallbackResult::Invalid(String::from(
+            "Tournament schedule has to be the round robin schedule of its players",
+        )));
+    }
+    if let Err(reason) = check_schedule(&tournament.schedule, &tournament.game_params) {
+        return Ok(ValidateCallbackResult::Invalid(format!(
+            "Can't create Tournament: {}",
+            reason
+        )));
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/// Checks the GameSession started for the tournament: it has to be played by one of
+/// the groups of its stage on the tournament game code with the tournament params,
+/// be started by the organizer, and only once the previous stage is over
+pub fn validate_tournament_session(
+    game_session: &GameSession,
+    tournament_match: &TournamentMatch,
+    author: &AgentPubKey,
+) -> ExternResult<Option<String>> {
+    let tournament = must_get_entry_struct::<Tournament>(tournament_match.tournament.clone())?;
+    if author != &tournament.organizer {
+        return Ok(Some(String::from(
+            "Only the organizer of the tournament can start its sessions",
+        )));
+    }
+    if game_session.anchor != calculate_game_code_anchor_entry_hash(tournament.game_code.clone())? {
+        return Ok(Some(String::from(
+            "GameSession has to be played on the game code of the tournament",
+        )));
+    }
+    let is_group = match tournament.schedule.get(tournament_match.stage as usize) {
+        Some(groups) => groups.contains(&game_session.players),
+        None => false,
+    };
+    if !is_group {
+        return Ok(Some(String::from(
+            "GameSession players have to be one of the groups of the tournament stage",
+        )));
+    }
+    if game_session.game_params != tournament.game_params {
+        return Ok(Some(String::from(
+            "GameSession has to be played with the game params of the tournament",
+        )));
+    }
+    check_previous_stage(&tournament, tournament_match)
+}
+
+/// Checks that the sessions the stage refers to are all the sessions of the stage
+/// before, and that every one of them has ended
+fn check_previous_stage(
+    tournament: &Tournament,
+    tournament_match: &TournamentMatch,
+) -> ExternResult<Option<String>> {
+    if tournament_match.stage == 0 {
+        if !tournament_match.previous_stage.is_empty() {
+            return Ok(Some(String::from(
+                "First stage of the tournament can't have a previous stage",
+            )));
+        }
+        return Ok(None);
+    }
+    let previous = tournament_match.stage - 1;
+    let mut groups = vec![];
+    for session_hash in tournament_match.previous_stage.iter() {
+        let session = must_get_entry_struct::<GameSession>(session_hash.clone())?;
+        let in_previous_stage = matches!(
+            &session.tournament,
+            Some(m) if m.tournament == tournament_match.tournament && m.stage == previous
+        );
+        if !in_previous_stage || session.status == SessionState::InProgress {
+            return Ok(Some(String::from(
+                "Stage of the tournament can only start once every game of the previous one ended",
+            )));
+        }
+        groups.push(session.players);
+    }
+    let mut expected_groups = tournament.schedule[previous as usize].clone();
+    groups.sort();
+    expected_groups.sort();
+    if groups != expected_groups {
+        return Ok(Some(String::from(
+            "Stage of the tournament has to refer to every game of the previous one",
+        )));
+    }
+    Ok(None)
+}
+
+/// Validates TOURNAMENT_SESSION link: it has to connect the tournament to one of its sessions
+pub fn validate_create_link_tournament_session(
+    data: &ValidateCreateLinkData,
+) -> ExternResult<ValidateLinkCallbackResult> {
+    let game_session = match GameSession::try_from(data.target.clone()) {
+        Ok(game_session) => game_session,
+        Err(_) => {
+            return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+                "TOURNAMENT_SESSION link has to target a GameSession entry",
+            )))
+        }
+    };
+    let belongs = matches!(
+        &game_session.tournament,
+        Some(m) if m.tournament == data.link_add.base_address
+    );
+    if !belongs {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "TOURNAMENT_SESSION link has to start from the tournament of the GameSession",
+        )));
+    }
+    if data.link_add.author != game_session.owner {
+        return Ok(ValidateLinkCallbackResult::Invalid(String::from(
+            "Only the owner of the GameSession can link it to the tournament",
+        )));
+    }
+    Ok(ValidateLinkCallbackResult::Valid)
+}
@@ -1,5 +1,6 @@
 use crate::{error::Error, game_session::GameSession};
 use hdk::prelude::*;
+use std::collections::HashSet;
 // NOTE: didn't had time to figure out how to apply this once on a lib level
 // TODO: remove it later
 #[allow(dead_code)]
@@ -84,6 +85,99 @@ where
     }
 }
 
+/// Follows the update chain of the entry with the given hash and returns entry hash,
+/// header hash and content of its latest version. When an entry has several updates,
+/// the most recent one is taken.
+/// The chain is followed by header hash: an update back to some earlier content has the
+/// entry hash of that earlier version, so following entry hashes could go round in circles.
+pub fn get_latest_entry<O>(entry_hash: EntryHash) -> ExternResult<(EntryHash, HeaderHash, O)>
+where
+    O: TryFrom<SerializedBytes, Error = SerializedBytesError>,
+{
+    let details = match get_details(entry_hash, GetOptions::latest())? {
+        Some(Details::Entry(details)) => details,
+        _ => return Err(Error::EntryMissing.into()),
+    };
+    // updates with the same content are headers of this entry as well, so the chain
+    // starts at the earliest header that created it
+    let mut current_header = match details
+        .headers
+        .iter()
+        .filter(|header| matches!(header.header(), Header::Create(_)))
+        .min_by_key(|header| header.header().timestamp())
+    {
+        Some(header) => header.header_address().clone(),
+        None => return Err(Error::WrongHeader.into()),
+    };
+    let mut visited: HashSet<HeaderHash> = HashSet::new();
+    loop {
+        visited.insert(current_header.clone());
+        let details = match get_details(current_header.clone(), GetOptions::latest())? {
+            Some(Details::Element(details)) => details,
+            _ => return Err(Error::EntryMissing.into()),
+        };
+        let latest_update = details
+            .updates
+            .iter()
+            .filter(|update| !visited.contains(update.header_address()))
+            .max_by_key(|update| update.header().timestamp());
+        match latest_update {
+            Some(update) => current_header = update.header_address().clone(),
+            None => {
+                let entry_hash = match details.element.header().entry_hash() {
+                    Some(entry_hash) => entry_hash.clone(),
+                    None => return Err(Error::EntryHashMissing.into()),
+                };
+                let entry: O = match details.element.entry().as_option() {
+                    Some(Entry::App(bytes)) => O::try_from(bytes.clone().into())
+                        .map_err(|e| WasmError::from(Error::Wasm(e.into())))?,
+                    _ => return Err(Error::EntryMissing.into()),
+                };
+                return Ok((entry_hash, current_header, entry));
+            }
+        }
+    }
+}
+
+/// Updates the entry created by header_hash to the new content and returns entry hash
+/// of the new content. Nothing is written when the content didn't change, so saving
+/// the same values again doesn't grow the update chain
+pub fn update_entry_if_changed<'a, T>(
+    header_hash: HeaderHash,
+    old_entry: &'a T,
+    new_entry: &'a T,
+) -> ExternResult<EntryHash>
+where
+    Entry: TryFrom<&'a T, Error = WasmError>,
+    EntryWithDefId: TryFrom<&'a T, Error = WasmError>,
+{
+    let new_entry_hash = hash_entry(new_entry)?;
+    if hash_entry(old_entry)? != new_entry_hash {
+        update_entry(header_hash, new_entry)?;
+    }
+    Ok(new_entry_hash)
+}
+
+/// Returns header hash and entry hash of the update of the given entry that every agent
+/// agrees on. When the entry was updated several times concurrently, the update with
+/// the lowest header hash wins, so forks in the update chain are resolved deterministically.
+pub fn get_canonical_update(entry_hash: EntryHash) -> ExternResult<Option<(HeaderHash, EntryHash)>> {
+    let details = match get_details(entry_hash, GetOptions::latest())? {
+        Some(Details::Entry(details)) => details,
+        _ => return Err(Error::EntryMissing.into()),
+    };
+    Ok(details
+        .updates
+        .iter()
+        .min_by(|a, b| a.header_address().cmp(b.header_address()))
+        .and_then(|update| {
+            update
+                .header()
+                .entry_hash()
+                .map(|hash| (update.header_address().clone(), hash.clone()))
+        }))
+}
+
 #[allow(dead_code)]
 pub fn enable_tracing(level: tracing::Level) {
     // i have no idea where to put the tracing config, as all examples suggest main
@@ -150,9 +244,6 @@ pub fn check_agent_is_player_current_session(game_session:GameSession) -> Extern
         return Ok(())
     } else {
         error!("No matched player found.");
-        return Err(WasmError::Guest(
-            "This player is not known in the game session. Probably joined the game to late."
-                .into(),
-        ))
+        return Err(Error::NotAPlayer.into())
     }
 }